@@ -16,33 +16,29 @@ use tokio::task::spawn_blocking;
 // Create an actor that prompts for input if the user is idle for too long
 #[derive(Default)]
 struct LonelyActor {
-    addr: WeakAddr<Self>,
     timer: Timer,
 }
 
 #[async_trait]
 impl Actor for LonelyActor {
     async fn started(&mut self, addr: Addr<Self>) -> ActorResult<()> {
-        // Store our own address for later
-        self.addr = addr.downgrade();
-
         // Start the timer
-        self.comfort().await;
+        send!(addr.comfort(_));
         Produces::ok(())
     }
 }
 
 impl LonelyActor {
-    async fn comfort(&mut self) {
+    async fn comfort(&mut self, ctx: &ActorContext<Self>) {
         println!(":)");
 
         // Schedule our "tick" method to be called in 10 seconds.
         // Timer methods have variations for "strong" and "weak" addresses: the former will keep
         // the actor alive as long as the timer is still active, whilst the latter will allow the
-        // actor to stop if there are no other references to it.
-        // In this case it doesn't really matter, but using the weak variation avoids a conversion.
-        self.timer
-            .set_timeout_for_weak(self.addr.clone(), Duration::from_secs(10));
+        // actor to stop if there are no other references to it. The context uses the latter.
+        // The actor's context provides its own address, so we don't need to store it. Passing
+        // `_` in place of the context when calling this method tells the actor to fill it in.
+        ctx.set_timeout_for(&mut self.timer, Duration::from_secs(10));
     }
 }
 
@@ -70,7 +66,7 @@ async fn main() -> Result<(), ActorError> {
         let mut input = String::new();
         loop {
             std::io::stdin().read_line(&mut input)?;
-            send!(addr.comfort());
+            send!(addr.comfort(_));
         }
     })
     .await?
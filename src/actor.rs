@@ -1,25 +1,156 @@
+use std::any::Any;
 use std::error::Error;
+use std::fmt;
 use std::future::Future;
-use std::mem;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use std::{iter, mem};
 
 use async_trait::async_trait;
 use futures::channel::oneshot;
-use futures::future::FutureExt;
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::select_biased;
 use log::error;
 
-use crate::Addr;
+use crate::timer::SupportsTimers;
+use crate::{Addr, SendError};
 
 /// The type of error returned by an actor method.
 pub type ActorError = Box<dyn Error + Send + Sync>;
 /// Short alias for a `Result<Produces<T>, ActorError>`.
 pub type ActorResult<T> = Result<Produces<T>, ActorError>;
 
-/// A concrete type similar to a `BoxFuture<'static, Result<T, oneshot::Canceled>>`, but
+/// Error returned when awaiting a `Produces<T>` which did not produce a value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ProducesError {
+    /// No value was sent, for example because the actor stopped or
+    /// the method returned an error.
+    Canceled,
+    /// The actor method panicked.
+    Panicked,
+    /// The deadline passed to `Produces::timeout` elapsed, or the method was
+    /// canceled by the actor's watchdog.
+    TimedOut,
+    /// Waiting for the value would have caused a deadlock. Only reported
+    /// when deadlock detection is enabled: see the `deadlock` module.
+    Deadlock,
+}
+
+impl fmt::Display for ProducesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProducesError::Canceled => f.write_str("No value was produced"),
+            ProducesError::Panicked => f.write_str("Actor method panicked"),
+            ProducesError::TimedOut => f.write_str("Timed out waiting for a value"),
+            ProducesError::Deadlock => f.write_str("Waiting for a value would deadlock"),
+        }
+    }
+}
+
+impl Error for ProducesError {}
+
+impl From<oneshot::Canceled> for ProducesError {
+    fn from(_: oneshot::Canceled) -> Self {
+        ProducesError::Canceled
+    }
+}
+
+/// Error returned by the `call_result!(...)` macro.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CallError<E> {
+    /// The method returned an error to the caller.
+    Handler(E),
+    /// The call could not be delivered because the actor is not running.
+    Closed,
+    /// The call was dropped because the actor's mailbox is full. The actor is
+    /// still running.
+    Full,
+    /// The actor method panicked.
+    Panicked,
+    /// No value was produced, for example because the actor stopped before
+    /// the call was processed, or the method returned an `ActorError`.
+    Canceled,
+    /// The call timed out.
+    TimedOut,
+    /// Waiting for the call would have caused a deadlock.
+    Deadlock,
+}
+
+impl<E> From<ProducesError> for CallError<E> {
+    fn from(error: ProducesError) -> Self {
+        match error {
+            ProducesError::Canceled => CallError::Canceled,
+            ProducesError::Panicked => CallError::Panicked,
+            ProducesError::TimedOut => CallError::TimedOut,
+            ProducesError::Deadlock => CallError::Deadlock,
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for CallError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallError::Handler(e) => write!(f, "Actor method failed: {}", e),
+            CallError::Closed => SendError::Disconnected.fmt(f),
+            CallError::Full => SendError::Full.fmt(f),
+            CallError::Panicked => ProducesError::Panicked.fmt(f),
+            CallError::Canceled => ProducesError::Canceled.fmt(f),
+            CallError::TimedOut => ProducesError::TimedOut.fmt(f),
+            CallError::Deadlock => ProducesError::Deadlock.fmt(f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for CallError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CallError::Handler(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Error passed to `Actor::error` when an actor method panics.
+#[derive(Debug, Clone)]
+pub struct PanicError {
+    message: Option<String>,
+}
+
+impl PanicError {
+    pub(crate) fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => Some(*message),
+            Err(payload) => payload.downcast_ref::<&str>().map(|&s| s.into()),
+        };
+        Self { message }
+    }
+    /// The message the method panicked with, if it was a string.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+impl fmt::Display for PanicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(message) = &self.message {
+            write!(f, "Actor method panicked: {}", message)
+        } else {
+            f.write_str("Actor method panicked")
+        }
+    }
+}
+
+impl Error for PanicError {}
+
+/// A concrete type similar to a `BoxFuture<'static, Result<T, ProducesError>>`, but
 /// without requiring an allocation if the value is immediately ready.
 /// This type implements the `Future` trait and can be directly `await`ed.
-#[derive(Debug)]
+///
+/// The combinators on this type also avoid allocating when the value is
+/// already available.
 #[non_exhaustive]
 pub enum Produces<T> {
     /// No value was produced.
@@ -28,6 +159,22 @@ pub enum Produces<T> {
     Value(T),
     /// A value may be sent in the future.
     Deferred(oneshot::Receiver<Produces<T>>),
+    /// The actor method panicked whilst producing the value.
+    Panicked,
+    /// A value will be produced by a boxed future.
+    Boxed(BoxFuture<'static, Result<T, ProducesError>>),
+}
+
+impl<T: fmt::Debug> fmt::Debug for Produces<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Produces::None => f.write_str("None"),
+            Produces::Value(value) => f.debug_tuple("Value").field(value).finish(),
+            Produces::Deferred(recv) => f.debug_tuple("Deferred").field(recv).finish(),
+            Produces::Panicked => f.write_str("Panicked"),
+            Produces::Boxed(_) => f.write_str("Boxed(..)"),
+        }
+    }
 }
 
 impl<T> Unpin for Produces<T> {}
@@ -39,30 +186,192 @@ impl<T> Produces<T> {
     }
 }
 
+impl<T: Send + 'static> Produces<T> {
+    pub(crate) fn from_result(res: Result<T, ProducesError>) -> Self {
+        match res {
+            Ok(value) => Produces::Value(value),
+            Err(ProducesError::Canceled) => Produces::None,
+            Err(ProducesError::Panicked) => Produces::Panicked,
+            Err(e) => Produces::Boxed(future::ready(Err(e)).boxed()),
+        }
+    }
+
+    /// Transform the value once it has been produced.
+    pub fn map<U: Send + 'static>(self, f: impl FnOnce(T) -> U + Send + 'static) -> Produces<U> {
+        match self {
+            Produces::Value(value) => Produces::Value(f(value)),
+            other => other.and_then(move |value| Produces::Value(f(value))),
+        }
+    }
+
+    /// Produce another value using the value produced by this one.
+    pub fn and_then<U: Send + 'static>(
+        self,
+        f: impl FnOnce(T) -> Produces<U> + Send + 'static,
+    ) -> Produces<U> {
+        match self {
+            Produces::None => Produces::None,
+            Produces::Value(value) => f(value),
+            Produces::Panicked => Produces::Panicked,
+            other => Produces::Boxed(
+                async move {
+                    match other.await {
+                        Ok(value) => f(value).await,
+                        Err(e) => Err(e),
+                    }
+                }
+                .boxed(),
+            ),
+        }
+    }
+
+    /// Produce another value if this one fails.
+    pub fn or_else(self, f: impl FnOnce(ProducesError) -> Produces<T> + Send + 'static) -> Self {
+        match self {
+            Produces::None => f(ProducesError::Canceled),
+            Produces::Value(value) => Produces::Value(value),
+            Produces::Panicked => f(ProducesError::Panicked),
+            other => Produces::Boxed(
+                async move {
+                    match other.await {
+                        Ok(value) => Ok(value),
+                        Err(e) => f(e).await,
+                    }
+                }
+                .boxed(),
+            ),
+        }
+    }
+
+    /// Fail with `ProducesError::TimedOut` if no value has been produced
+    /// once `timeout` has elapsed, as measured by the runtime's clock.
+    pub fn timeout<S: SupportsTimers>(self, runtime: &S, timeout: Duration) -> Self {
+        self.timeout_at(runtime, runtime.now() + timeout)
+    }
+
+    /// Fail with `ProducesError::TimedOut` if no value has been produced
+    /// by the time the runtime's clock reaches `deadline`.
+    pub fn timeout_at<S: SupportsTimers>(self, runtime: &S, deadline: Instant) -> Self {
+        match self {
+            Produces::None | Produces::Value(_) | Produces::Panicked => self,
+            other => {
+                let delay = runtime.delay(deadline);
+                Produces::Boxed(
+                    async move {
+                        select_biased! {
+                            res = other.fuse() => res,
+                            _ = delay.fuse() => Err(ProducesError::TimedOut),
+                        }
+                    }
+                    .boxed(),
+                )
+            }
+        }
+    }
+
+    /// Produce all of the values, in order, or fail as soon as any of
+    /// them fail.
+    pub fn join_all(iter: impl IntoIterator<Item = Self>) -> Produces<Vec<T>> {
+        let mut values = Vec::new();
+        let mut pending = iter.into_iter();
+        for item in &mut pending {
+            match item {
+                Produces::Value(value) => values.push(value),
+                other => {
+                    let rest: Vec<_> = iter::once(other).chain(pending).collect();
+                    return Produces::Boxed(
+                        async move {
+                            values.extend(future::try_join_all(rest).await?);
+                            Ok(values)
+                        }
+                        .boxed(),
+                    );
+                }
+            }
+        }
+        Produces::Value(values)
+    }
+
+    /// Produce the first value to become available. Fails only if all of
+    /// them fail, in which case the last error is returned.
+    pub fn select(iter: impl IntoIterator<Item = Self>) -> Self {
+        let mut rest = Vec::new();
+        let mut error = ProducesError::Canceled;
+        for item in iter {
+            match item {
+                Produces::Value(value) => return Produces::Value(value),
+                Produces::None => error = ProducesError::Canceled,
+                Produces::Panicked => error = ProducesError::Panicked,
+                other => rest.push(other),
+            }
+        }
+        if rest.is_empty() {
+            return Self::from_result(Err(error));
+        }
+        Produces::Boxed(
+            async move { future::select_ok(rest).await.map(|(value, _)| value) }.boxed(),
+        )
+    }
+}
+
 impl<T> Future for Produces<T> {
-    type Output = Result<T, oneshot::Canceled>;
+    type Output = Result<T, ProducesError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         loop {
             break match mem::replace(&mut *self, Produces::None) {
-                Produces::None => Poll::Ready(Err(oneshot::Canceled)),
+                Produces::None => Poll::Ready(Err(ProducesError::Canceled)),
                 Produces::Value(value) => Poll::Ready(Ok(value)),
+                Produces::Panicked => Poll::Ready(Err(ProducesError::Panicked)),
                 Produces::Deferred(mut recv) => match recv.poll_unpin(cx) {
                     Poll::Ready(Ok(producer)) => {
                         *self = producer;
                         continue;
                     }
-                    Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                    Poll::Ready(Err(e)) => Poll::Ready(Err(e.into())),
                     Poll::Pending => {
                         *self = Produces::Deferred(recv);
                         Poll::Pending
                     }
                 },
+                Produces::Boxed(mut fut) => match fut.poll_unpin(cx) {
+                    Poll::Ready(res) => Poll::Ready(res),
+                    Poll::Pending => {
+                        *self = Produces::Boxed(fut);
+                        Poll::Pending
+                    }
+                },
             };
         }
     }
 }
 
+/// The reason an actor stopped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ExitReason {
+    /// The actor was stopped using `Addr::stop`, or there were no
+    /// remaining strong references to it.
+    Normal,
+    /// An actor method returned an error, and `Actor::error` asked for
+    /// the actor to stop.
+    Error,
+    /// A lifecycle method panicked. Panics in other actor methods are
+    /// passed to `Actor::error` as a `PanicError`.
+    Panicked,
+    /// The actor was stopped using `Addr::kill`, or its task was
+    /// dropped by the executor.
+    Killed,
+}
+
+impl ExitReason {
+    /// Returns `true` if the actor stopped for any reason other than
+    /// a graceful stop.
+    pub fn is_abnormal(&self) -> bool {
+        *self != ExitReason::Normal
+    }
+}
+
 /// Trait implemented by all actors.
 /// This trait is defined using the `#[async_trait]` attribute:
 /// ```ignore
@@ -85,6 +394,19 @@ impl<T> Future for Produces<T> {
 ///         error!("{}", error);
 ///         true
 ///     }
+///
+///     /// Called when the actor is about to stop, unless it was killed.
+///     /// If the actor is being stopped via `Addr::stop`, it can return
+///     /// `false` to continue running. Otherwise the return value is ignored:
+///     /// in particular, an actor stopping because `error` returned `true`
+///     /// cannot veto it here, and should return `false` from `error` instead.
+///     async fn stopping(&mut self, _reason: ExitReason) -> bool {
+///         true
+///     }
+///
+///     /// Called when the actor has stopped, unless it was killed. The
+///     /// actor's state will be dropped once this method returns.
+///     async fn stopped(&mut self, _reason: ExitReason) {}
 /// }
 /// ```
 ///
@@ -93,7 +415,8 @@ impl<T> Future for Produces<T> {
 #[async_trait]
 pub trait Actor: Send + 'static {
     /// Called automatically when an actor is started. Actors can use this
-    /// to store their own address for future use.
+    /// to store their own address for future use, although `ActorContext`
+    /// can be passed to any method which needs it instead.
     async fn started(&mut self, _addr: Addr<Self>) -> ActorResult<()>
     where
         Self: Sized,
@@ -109,6 +432,45 @@ pub trait Actor: Send + 'static {
         error!("{}", error);
         true
     }
+
+    /// Called when the actor is about to stop, unless it was killed.
+    /// If the actor is being stopped via `Addr::stop`, it can return
+    /// `false` to continue running. Otherwise the return value is ignored:
+    /// in particular, an actor stopping because `error` returned `true`
+    /// cannot veto it here, and should return `false` from `error` instead.
+    async fn stopping(&mut self, _reason: ExitReason) -> bool {
+        true
+    }
+
+    /// Called when the actor has stopped, unless it was killed. The
+    /// actor's state will be dropped once this method returns.
+    async fn stopped(&mut self, _reason: ExitReason) {}
+}
+
+/// Allows a stream of items to be attached to an actor using `AddrLike::add_stream`.
+///
+/// This trait is defined using the `#[async_trait]` attribute as follows:
+/// ```ignore
+/// #[async_trait]
+/// pub trait StreamHandler<I>: Actor {
+///     /// Called for each item produced by the stream.
+///     async fn handle(&mut self, item: I) -> ActorResult<()>;
+///
+///     /// Called once the stream has ended.
+///     async fn finished(&mut self) -> ActorResult<()> {
+///         Produces::ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait StreamHandler<I: Send + 'static>: Actor {
+    /// Called for each item produced by the stream.
+    async fn handle(&mut self, item: I) -> ActorResult<()>;
+
+    /// Called once the stream has ended.
+    async fn finished(&mut self) -> ActorResult<()> {
+        Produces::ok(())
+    }
 }
 
 /// Actor methods may return any type implementing this trait.
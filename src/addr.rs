@@ -1,118 +1,698 @@
 use std::any::Any;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::{self, Debug};
 use std::future::Future;
 use std::hash::{Hash, Hasher};
-use std::sync::{Arc, Weak};
-use std::{mem, ptr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use std::{mem, thread};
 
 use futures::channel::{mpsc, oneshot};
-use futures::future::{self, BoxFuture, FutureExt};
+use futures::future::{self, AbortHandle, BoxFuture, FutureExt};
 use futures::select_biased;
-use futures::stream::{FuturesUnordered, StreamExt};
+use futures::stream::{FusedStream, FuturesUnordered, Stream, StreamExt};
 use futures::task::{Spawn, SpawnError, SpawnExt};
 
-use crate::{send, Actor, Produces, Termination};
+use crate::deadlock;
+use crate::mailbox::{Mailbox, PriorityReceiver};
+use crate::metrics::{self, MessageMetrics};
+use crate::stats::{ActorStats, Stats};
+use crate::timer::SupportsTimers;
+use crate::watchdog::{HandlerTimeout, Watchdog};
+use crate::{
+    Actor, ActorContext, ActorError, ExitReason, MonitorId, PanicError, Produces, ProducesError,
+    SendError, StreamHandler, Termination,
+};
 
-type MutItem<T> = Box<dyn for<'a> FnOnce(&'a mut T) -> BoxFuture<'a, bool> + Send>;
+pub(crate) type RunFn<T> =
+    Box<dyn for<'a> FnOnce(&'a mut T, ActorContext<T>) -> BoxFuture<'a, Option<ExitReason>> + Send>;
+pub(crate) type RefRunFn<T> =
+    Box<dyn for<'a> FnOnce(&'a T, ActorContext<T>) -> BoxFuture<'a, Option<ActorError>> + Send>;
 type FutItem = BoxFuture<'static, ()>;
+type SendMutFn<T> = dyn Fn(&Arc<ErasedInner>, MutItem<T>) -> Result<(), SendError> + Send + Sync;
+type SendFutFn = dyn Fn(&Arc<ErasedInner>, FutItem) + Send + Sync;
+pub(crate) type RestartFn<T> =
+    Box<dyn FnMut(ExitReason, ActorContext<T>) -> BoxFuture<'static, Option<T>> + Send>;
 
-async fn mutex_task<T>(
-    value: T,
-    mut mut_channel: mpsc::UnboundedReceiver<MutItem<T>>,
-    mut fut_channel: mpsc::UnboundedReceiver<FutItem>,
-) {
-    let mut futs = FuturesUnordered::new();
-    // Re-bind 'value' so that it is dropped before futs.
-    // That will ensure .termination() completes only once the value's drop has finished.
-    let mut value = value;
-    loop {
-        // Obtain an item
-        let current_item = loop {
-            if select_biased! {
-                _ = futs.select_next_some() => false,
-                item = mut_channel.next() => if let Some(item) = item {
-                    break item
-                } else {
-                    true
-                },
-                item = fut_channel.select_next_some() => {
-                    futs.push(item);
-                    false
-                },
-                complete => true,
-            } {
-                return;
+/// Uniquely identifies a spawned actor for the lifetime of the process.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ActorId(u64);
+
+impl ActorId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, AtomicOrdering::Relaxed))
+    }
+}
+
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+// The parts of an actor's state which remain available after it has stopped,
+// so that weak addresses can identify the actor and report why it stopped
+// without keeping the rest of its state alive.
+#[derive(Debug)]
+pub(crate) struct Identity {
+    pub(crate) id: ActorId,
+    pub(crate) name: Option<Arc<str>>,
+    exit_reason: Mutex<Option<ExitReason>>,
+}
+
+impl Identity {
+    pub(crate) fn exit_reason(&self) -> Option<ExitReason> {
+        *self.exit_reason.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// State shared between the actor task and all strong addresses of the actor.
+// Weak addresses only hold a weak reference to it.
+#[derive(Debug)]
+pub(crate) struct Shared {
+    pub(crate) identity: Arc<Identity>,
+    // The name of the actor type.
+    pub(crate) actor: &'static str,
+    pub(crate) mailbox: Arc<Mailbox>,
+    pub(crate) stats: Stats,
+    watchdog: Option<Watchdog>,
+    detect_deadlocks: bool,
+    kill: Mutex<Option<oneshot::Sender<()>>>,
+    // Monitors created by this actor which have yet to fire.
+    monitors: Mutex<HashMap<MonitorId, AbortHandle>>,
+}
+
+impl Shared {
+    pub(crate) fn exit_reason(&self) -> Option<ExitReason> {
+        self.identity.exit_reason()
+    }
+    fn kill(&self) {
+        if let Some(tx) = self.kill.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            let _ = tx.send(());
+        }
+    }
+    pub(crate) fn monitors(&self) -> MutexGuard<'_, HashMap<MonitorId, AbortHandle>> {
+        self.monitors.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn rearm_kill(&self) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        *self.kill.lock().unwrap_or_else(|e| e.into_inner()) = Some(tx);
+        rx
+    }
+    // Runs a method of the actor, under the watchdog if there is one.
+    pub(crate) async fn watch<F: Future>(
+        &self,
+        method: &'static str,
+        fut: F,
+    ) -> Result<F::Output, HandlerTimeout> {
+        match &self.watchdog {
+            Some(watchdog) => watchdog.watch(self, method, fut).await,
+            None => Ok(fut.await),
+        }
+    }
+}
+
+// Records why the actor stopped and closes its mailbox, even if the actor
+// task panics or is dropped by the executor.
+struct ExitGuard {
+    actor: &'static str,
+    shared: Arc<Shared>,
+    reason: Option<ExitReason>,
+}
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        let reason = self.reason.take().unwrap_or(if thread::panicking() {
+            ExitReason::Panicked
+        } else {
+            ExitReason::Killed
+        });
+        *self
+            .shared
+            .identity
+            .exit_reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(reason);
+        self.shared.mailbox.close();
+        metrics::record_stopped(self.actor, &self.shared);
+    }
+}
+
+// Channels and futures owned by the actor task, which outlive any individual
+// instance of a restartable actor.
+struct Task<T: ?Sized + 'static> {
+    ctx: ActorContext<T>,
+    kill: oneshot::Receiver<()>,
+    mut_channel: PriorityReceiver<MutItem<T>>,
+    fut_channel: mpsc::UnboundedReceiver<FutItem>,
+    futs: Futures,
+    // An item which was received whilst running shared items, and which must
+    // wait for them to complete.
+    pending: Option<MutItem<T>>,
+}
+
+// The futures spawned onto an actor, which keeps count of them in the actor's stats.
+struct Futures {
+    futs: FuturesUnordered<FutItem>,
+    shared: Arc<Shared>,
+}
+
+impl Futures {
+    fn push(&mut self, item: FutItem) {
+        self.futs.push(item);
+        self.shared.stats.set_futures(self.futs.len());
+    }
+}
+
+impl Stream for Futures {
+    type Item = ();
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<()>> {
+        let res = self.futs.poll_next_unpin(cx);
+        if let Poll::Ready(Some(())) = res {
+            self.shared.stats.set_futures(self.futs.len());
+        }
+        res
+    }
+}
+
+impl FusedStream for Futures {
+    fn is_terminated(&self) -> bool {
+        self.futs.is_terminated()
+    }
+}
+
+impl<T: Actor> Task<T> {
+    // Runs the actor until it stops, returning the reason.
+    async fn run(&mut self, value: &mut T, shared: &Shared) -> ExitReason {
+        // Methods which stop the actor are responsible for notifying it first.
+        let mut stopping_notified = false;
+        let reason = 'outer: loop {
+            // Obtain an item
+            let current_item = if let Some(item) = self.pending.take() {
+                item
+            } else {
+                loop {
+                    select_biased! {
+                        _ = &mut self.kill => break 'outer ExitReason::Killed,
+                        _ = self.futs.select_next_some() => {},
+                        item = self.mut_channel.next() => if let Some(item) = item {
+                            shared.mailbox.pop();
+                            metrics::record_queue_depth(std::any::type_name::<T>(), shared);
+                            break item
+                        } else {
+                            break 'outer ExitReason::Normal
+                        },
+                        item = self.fut_channel.select_next_some() => self.futs.push(item),
+                        complete => break 'outer ExitReason::Normal,
+                    }
+                }
+            };
+            let current_item = match current_item.discard_stale() {
+                Some(item) => item,
+                None => continue,
+            };
+
+            // Wait for the current item to run
+            let MutItem { run, metrics, .. } = current_item;
+            let began = shared.stats.begin();
+            let res = match run {
+                Run::Mut(run) => {
+                    let errors = shared.stats.errors();
+                    let id = shared.detect_deadlocks.then_some(shared.identity.id);
+                    let fut = deadlock::Handler::new(id, run(value, self.ctx.clone()));
+                    let res = self.run_until(fut).await;
+                    if res.is_some() {
+                        shared.stats.record_message();
+                        if let Some(metrics) = metrics {
+                            metrics.handled(began, shared.stats.errors() > errors);
+                        }
+                    }
+                    res
+                }
+                Run::Ref(run) => self.run_shared(value, run, metrics, shared).await,
+            };
+            shared.stats.end(began);
+            match res {
+                None => break ExitReason::Killed,
+                Some(Some(reason)) => {
+                    stopping_notified = true;
+                    break reason;
+                }
+                Some(None) => {}
+            }
+        };
+
+        // Give the actor a chance to clean up, whilst still running its futures.
+        if reason == ExitReason::Killed {
+            return reason;
+        }
+        let cleanup = async {
+            if !stopping_notified {
+                value.stopping(reason).await;
             }
+            value.stopped(reason).await;
         };
+        self.run_until(cleanup)
+            .await
+            .map_or(ExitReason::Killed, |()| reason)
+    }
+
+    // Runs an item with shared access to the actor, along with any further such
+    // items received before the next item which requires exclusive access. That
+    // item is left in `self.pending`. Errors are passed to the actor once all of
+    // the shared items have completed. Returns `None` if the actor is killed first.
+    async fn run_shared(
+        &mut self,
+        value: &mut T,
+        first: RefRunFn<T>,
+        metrics: Option<MessageMetrics>,
+        shared: &Shared,
+    ) -> Option<Option<ExitReason>> {
+        let Task {
+            ctx,
+            kill,
+            mut_channel,
+            fut_channel,
+            futs,
+            pending,
+        } = self;
+        let mut errors = Vec::new();
+        {
+            let mut running = FuturesUnordered::new();
+            running.push(run_ref(first(value, ctx.clone()), metrics));
+            while !running.is_empty() {
+                // Stop accepting items once one has to wait, so that exclusive
+                // items are not starved by a steady stream of shared items.
+                let mut next_item = if pending.is_none() && errors.is_empty() {
+                    mut_channel.next().left_future()
+                } else {
+                    future::pending().right_future()
+                };
+                select_biased! {
+                    _ = &mut *kill => return None,
+                    error = running.select_next_some() => {
+                        shared.stats.record_message();
+                        errors.extend(error);
+                    },
+                    _ = futs.select_next_some() => {},
+                    item = next_item => if let Some(item) = item {
+                        shared.mailbox.pop();
+                        metrics::record_queue_depth(std::any::type_name::<T>(), shared);
+                        let item = match item.discard_stale() {
+                            Some(item) => item,
+                            None => continue,
+                        };
+                        match item.run {
+                            Run::Ref(run) => {
+                                running.push(run_ref(run(value, ctx.clone()), item.metrics))
+                            }
+                            run => *pending = Some(MutItem { run, ..item }),
+                        }
+                    },
+                    item = fut_channel.select_next_some() => futs.push(item),
+                }
+            }
+        }
+        let ctx = self.ctx.clone();
+        for error in errors {
+            let reason = self
+                .run_until(crate::hidden::handle_error(value, &ctx, error))
+                .await?;
+            if reason.is_some() {
+                return Some(reason);
+            }
+        }
+        Some(None)
+    }
 
-        // Wait for the current item to run
-        let mut current_future = current_item(&mut value).fuse();
+    // Runs a future to completion whilst also running the actor's futures.
+    // Returns `None` if the actor is killed first.
+    async fn run_until<F: Future>(&mut self, f: F) -> Option<F::Output> {
+        let f = f.fuse();
+        futures::pin_mut!(f);
         loop {
             select_biased! {
-                done = current_future => if done {
-                    return;
-                } else {
-                    break
-                },
-                _ = futs.select_next_some() => {},
-                item = fut_channel.select_next_some() => futs.push(item),
+                _ = &mut self.kill => break None,
+                res = f => break Some(res),
+                _ = self.futs.select_next_some() => {},
+                item = self.fut_channel.select_next_some() => self.futs.push(item),
             }
         }
     }
 }
 
-struct AddrInner<T> {
-    mut_channel: mpsc::UnboundedSender<MutItem<T>>,
+// Runs an item with shared access to the actor, recording its metrics once it completes.
+async fn run_ref(
+    fut: BoxFuture<'_, Option<ActorError>>,
+    metrics: Option<MessageMetrics>,
+) -> Option<ActorError> {
+    let began = Instant::now();
+    let error = fut.await;
+    if let Some(metrics) = metrics {
+        metrics.handled(began, error.is_some());
+    }
+    error
+}
+
+async fn mutex_task<T: Actor>(
+    value: T,
+    ctx: ActorContext<T>,
+    shared: Arc<Shared>,
+    kill: oneshot::Receiver<()>,
+    mut_channel: PriorityReceiver<MutItem<T>>,
+    fut_channel: mpsc::UnboundedReceiver<FutItem>,
+    mut restart: Option<RestartFn<T>>,
+) {
+    let mut task = Task {
+        ctx,
+        kill,
+        mut_channel,
+        fut_channel,
+        futs: Futures {
+            futs: FuturesUnordered::new(),
+            shared: shared.clone(),
+        },
+        pending: None,
+    };
+    let mut guard = ExitGuard {
+        actor: std::any::type_name::<T>(),
+        shared,
+        reason: None,
+    };
+    // Bind 'value' after the task and guard so that it is dropped first.
+    // That will ensure .termination() completes only once the value's drop has finished
+    // and the exit reason has been recorded.
+    let mut value = Some(value);
+    let reason = loop {
+        let reason = if let Some(value) = &mut value {
+            task.run(value, &guard.shared).await
+        } else {
+            break ExitReason::Normal;
+        };
+        if let Some(restart) = restart.as_mut().filter(|_| reason.is_abnormal()) {
+            // Drop the failed instance before creating its replacement. Killing the
+            // actor whilst it is being restarted stops it for good.
+            drop(value.take());
+            if reason == ExitReason::Killed {
+                task.kill = guard.shared.rearm_kill();
+            }
+            match task.run_until(restart(reason, task.ctx.clone())).await {
+                Some(Some(replacement)) => {
+                    value = Some(replacement);
+                    continue;
+                }
+                Some(None) => {}
+                None => break ExitReason::Killed,
+            }
+        }
+        break reason;
+    };
+    guard.reason = Some(reason);
+}
+
+struct AddrInner<C: ?Sized> {
+    shared: Arc<Shared>,
+    channels: C,
+}
+
+type ErasedInner = AddrInner<dyn Any + Send + Sync>;
+
+struct Channels<T: 'static> {
+    // One channel for each priority level, lowest priority first.
+    mut_channels: Vec<mpsc::UnboundedSender<MutItem<T>>>,
     fut_channel: mpsc::UnboundedSender<FutItem>,
 }
 
-impl<T: 'static> AddrInner<T> {
-    fn send_mut(this: &Arc<dyn Any + Send + Sync>, item: MutItem<T>) {
-        this.downcast_ref::<Self>()
+impl<T: Send + 'static> Channels<T> {
+    fn send_item<U: ?Sized>(
+        &self,
+        item: MutItem<U>,
+        f: impl FnOnce(MutItem<U>) -> MutItem<T>,
+    ) -> Result<(), SendError> {
+        let level = item.priority.min(self.mut_channels.len() - 1);
+        self.mut_channels[level]
+            .unbounded_send(f(item))
+            .map_err(|_| SendError::Disconnected)
+    }
+    fn send_mut(this: &Arc<ErasedInner>, item: MutItem<T>) -> Result<(), SendError> {
+        this.channels
+            .downcast_ref::<Self>()
             .unwrap()
-            .mut_channel
-            .unbounded_send(item)
-            .ok();
+            .send_item(item, |item| item)?;
+        metrics::record_queue_depth(std::any::type_name::<T>(), &this.shared);
+        Ok(())
     }
-    fn send_fut(this: &Arc<dyn Any + Send + Sync>, item: FutItem) {
-        this.downcast_ref::<Self>()
+    fn send_fut(this: &Arc<ErasedInner>, item: FutItem) {
+        this.channels
+            .downcast_ref::<Self>()
             .unwrap()
             .fut_channel
             .unbounded_send(item)
             .ok();
     }
 
-    // Must only be called if we have previously encountered a witness value of type `F`.
-    fn send_mut_upcasted<U: ?Sized + 'static, F: Fn(&mut T) -> &mut U + Copy + Send>(
-        this: &Arc<dyn Any + Send + Sync>,
+    // Must only be called if we have previously encountered witness values of types `F` and `G`.
+    fn send_mut_upcasted<
+        U: ?Sized + Send + 'static,
+        F: Fn(&mut T) -> &mut U + Copy + Send + 'static,
+        G: Fn(&T) -> &U + Copy + Send + 'static,
+    >(
+        this: &Arc<ErasedInner>,
         item: MutItem<U>,
-    ) {
+    ) -> Result<(), SendError> {
         assert_eq!(mem::size_of::<F>(), 0);
+        assert_eq!(mem::size_of::<G>(), 0);
 
-        this.downcast_ref::<Self>()
+        this.channels
+            .downcast_ref::<Self>()
             .unwrap()
-            .mut_channel
-            .unbounded_send(Box::new(move |x| {
-                let f: F = unsafe { mem::zeroed() };
-                item(f(x))
-            }))
-            .ok();
+            .send_item(item, |item| MutItem {
+                priority: item.priority,
+                metrics: item.metrics,
+                deadline: item.deadline,
+                caller: item.caller,
+                run: match item.run {
+                    Run::Mut(run) => Run::Mut(Box::new(move |x, ctx| {
+                        let f: F = unsafe { mem::zeroed() };
+                        let g: G = unsafe { mem::zeroed() };
+                        run(f(x), ctx.upcast(f, g))
+                    })),
+                    Run::Ref(run) => Run::Ref(Box::new(move |x, ctx| {
+                        let f: F = unsafe { mem::zeroed() };
+                        let g: G = unsafe { mem::zeroed() };
+                        run(g(x), ctx.upcast(f, g))
+                    })),
+                },
+            })?;
+        metrics::record_queue_depth(std::any::type_name::<T>(), &this.shared);
+        Ok(())
     }
 }
 
-fn send_unreachable<T>(_: &Arc<dyn Any + Send + Sync>, _: T) {
+// Sends an item which has already been counted by the mailbox, removing it
+// from the count again if the actor has stopped.
+fn send_queued<T: ?Sized>(
+    send_mut: &SendMutFn<T>,
+    inner: &Arc<ErasedInner>,
+    item: MutItem<T>,
+) -> Result<(), SendError> {
+    send_mut(inner, item).map_err(|e| {
+        inner.shared.mailbox.pop();
+        e
+    })
+}
+
+// Sends a message which controls the actor itself, ignoring the capacity of
+// its mailbox.
+fn send_unbounded<T: ?Sized>(send_mut: &SendMutFn<T>, inner: &Arc<ErasedInner>, item: MutItem<T>) {
+    if inner.shared.mailbox.push_unbounded().is_ok() {
+        let _ = send_queued(send_mut, inner, item);
+    }
+}
+
+fn send_unreachable<T, R>(_: &Arc<ErasedInner>, _: T) -> R {
     unreachable!()
 }
 
+enum Run<T: ?Sized + 'static> {
+    // Requires exclusive access to the actor.
+    Mut(RunFn<T>),
+    // Only requires shared access, so may run concurrently with other such items.
+    Ref(RefRunFn<T>),
+}
+
+/// Sends the result of a call back to the caller.
+#[doc(hidden)]
+pub struct Reply<T> {
+    tx: Arc<Mutex<Option<oneshot::Sender<Produces<T>>>>>,
+}
+
+impl<T> Clone for Reply<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> Reply<T> {
+    pub(crate) fn new() -> (Self, oneshot::Receiver<Produces<T>>) {
+        let (tx, rx) = oneshot::channel();
+        (
+            Self {
+                tx: Arc::new(Mutex::new(Some(tx))),
+            },
+            rx,
+        )
+    }
+    fn take(&self) -> Option<oneshot::Sender<Produces<T>>> {
+        self.tx.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+    /// Sends the result to the caller, if they are still waiting for it.
+    pub fn send(&self, res: Produces<T>) {
+        if let Some(tx) = self.take() {
+            let _ = tx.send(res);
+        }
+    }
+    /// Tells the caller that the method failed. Panics are reported as such,
+    /// methods canceled by the watchdog are reported as having timed out, and
+    /// any other error leaves the call canceled.
+    pub fn failed(&self, error: &ActorError) {
+        if let Some(tx) = self.take() {
+            if error.is::<PanicError>() {
+                let _ = tx.send(Produces::Panicked);
+            } else if error.is::<HandlerTimeout>() {
+                let _ = tx.send(Produces::from_result(Err(ProducesError::TimedOut)));
+            }
+        }
+    }
+}
+
+// The caller of a queued call, with the type of the result erased.
+trait Caller: Send + Sync {
+    fn is_canceled(&self) -> bool;
+    fn timed_out(&self);
+}
+
+impl<T: Send + 'static> Caller for Reply<T> {
+    fn is_canceled(&self) -> bool {
+        self.tx
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .map_or(true, oneshot::Sender::is_canceled)
+    }
+    fn timed_out(&self) {
+        self.send(Produces::from_result(Err(ProducesError::TimedOut)));
+    }
+}
+
+/// A message queued for an actor.
+#[doc(hidden)]
+pub struct MutItem<T: ?Sized + 'static> {
+    priority: usize,
+    run: Run<T>,
+    metrics: Option<MessageMetrics>,
+    // Messages which are still queued after this time are discarded.
+    deadline: Option<Deadline>,
+    // The caller waiting for the result, if this is a call.
+    caller: Option<Box<dyn Caller>>,
+}
+
+impl<T: ?Sized> MutItem<T> {
+    fn with_run(priority: usize, run: Run<T>) -> Self {
+        Self {
+            priority,
+            run,
+            metrics: None,
+            deadline: None,
+            caller: None,
+        }
+    }
+    pub(crate) fn new(priority: usize, run: RunFn<T>) -> Self {
+        Self::with_run(priority, Run::Mut(run))
+    }
+    pub(crate) fn new_ref(priority: usize, run: RefRunFn<T>) -> Self {
+        Self::with_run(priority, Run::Ref(run))
+    }
+    // Records metrics for this message against the given method.
+    pub(crate) fn with_metrics(mut self, key: metrics::MethodKey) -> Self {
+        self.metrics = MessageMetrics::new(key);
+        self
+    }
+    pub(crate) fn with_deadline(mut self, deadline: Option<Deadline>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+    pub(crate) fn with_reply<R: Send + 'static>(mut self, reply: Reply<R>) -> Self {
+        self.caller = Some(Box::new(reply));
+        self
+    }
+    // Returns `None` if the message should be discarded without running, because
+    // its deadline has passed or the caller has given up on it. Discarded messages
+    // are recorded as dropped.
+    fn discard_stale(self) -> Option<Self> {
+        if self.deadline.as_ref().is_some_and(Deadline::has_passed) {
+            if let Some(caller) = &self.caller {
+                caller.timed_out();
+            }
+            None
+        } else if self
+            .caller
+            .as_ref()
+            .is_some_and(|caller| caller.is_canceled())
+        {
+            None
+        } else {
+            Some(self)
+        }
+    }
+}
+
+fn stop_item<T: Actor + ?Sized>() -> MutItem<T> {
+    MutItem::<T>::new(
+        0,
+        Box::new(|x, _ctx| {
+            async move {
+                if x.stopping(ExitReason::Normal).await {
+                    Some(ExitReason::Normal)
+                } else {
+                    None
+                }
+            }
+            .boxed()
+        }),
+    )
+}
+
 /// Trait provides methods for spawning futures onto an actor. Implemented by
 /// `Addr` and `WeakAddr` alike.
+///
+/// The hidden methods of this trait are used by the macros, and may change
+/// between releases. Types outside of this crate which implement the trait
+/// should forward them to an address they wrap.
 pub trait AddrLike: Send + Sync + Clone + Debug + 'static + AsAddr<Addr = Self> {
     /// Type of the actor reference by this address.
     type Actor: Actor + ?Sized;
 
     #[doc(hidden)]
-    fn send_mut(&self, item: MutItem<Self::Actor>);
+    fn send_mut(&self, item: MutItem<Self::Actor>) -> Result<(), SendError>;
+
+    // Waits for space in the actor's mailbox. By default the item is sent
+    // straight away, as with `send_mut`.
+    #[doc(hidden)]
+    fn send_mut_async(
+        &self,
+        item: MutItem<Self::Actor>,
+    ) -> BoxFuture<'static, Result<(), SendError>> {
+        future::ready(self.send_mut(item)).boxed()
+    }
 
     /// Spawn a future onto the actor which does not return a value.
     fn send_fut(&self, fut: impl Future<Output = ()> + Send + 'static);
@@ -149,11 +729,296 @@ pub trait AddrLike: Send + Sync + Clone + Debug + 'static + AsAddr<Addr = Self>
         self.call_fut(f(self.clone()))
     }
 
-    /// Returns a future which resolves when the actor terminates. If the
-    /// actor has already terminated, or if this address is detached, the
-    /// future will resolve immediately.
+    #[doc(hidden)]
+    fn deadline(&self) -> Option<Deadline> {
+        None
+    }
+
+    #[doc(hidden)]
+    fn priority(&self) -> usize {
+        0
+    }
+
+    #[doc(hidden)]
+    fn actor_id(&self) -> Option<ActorId> {
+        None
+    }
+
+    /// Returns an address which sends messages at the given priority. Messages
+    /// with a higher priority are processed before any queued messages with a
+    /// lower priority. Messages with the same priority are processed in the
+    /// order they were sent.
+    ///
+    /// Priorities beyond the number of levels the actor was spawned with are
+    /// treated as the highest level. See `SpawnOptions::priorities`.
+    ///
+    /// ```ignore
+    /// send!(addr.with_priority(1).reload_config());
+    /// ```
+    fn with_priority(&self, priority: usize) -> WithPriority<Self> {
+        WithPriority {
+            addr: self.clone(),
+            priority,
+        }
+    }
+
+    /// Returns an address which attaches a deadline to each message sent through
+    /// it. Messages which are still queued once the deadline has passed are
+    /// discarded without being run, and callers waiting on the result receive
+    /// a `ProducesError::TimedOut`. The deadline is measured using the clock of
+    /// the given runtime.
+    ///
+    /// ```ignore
+    /// call!(addr.with_deadline(&Runtime, deadline).method()).await
+    /// ```
+    fn with_deadline<R>(&self, runtime: &R, deadline: Instant) -> WithDeadline<Self>
+    where
+        R: SupportsTimers + Clone + Send + Sync + 'static,
+    {
+        WithDeadline {
+            addr: self.clone(),
+            deadline: Deadline::new(runtime.clone(), deadline),
+        }
+    }
+
+    /// Equivalent to `with_deadline`, with a deadline relative to the current time.
+    fn with_timeout<R>(&self, runtime: &R, timeout: Duration) -> WithDeadline<Self>
+    where
+        R: SupportsTimers + Clone + Send + Sync + 'static,
+    {
+        self.with_deadline(runtime, runtime.now() + timeout)
+    }
+
+    /// Attach a stream to the actor. Each item produced by the stream is passed to
+    /// `StreamHandler::handle`, and the next item is not requested until the handler
+    /// has returned. Once the stream ends, `StreamHandler::finished` is called.
+    ///
+    /// The stream is dropped if the actor stops. The stream holds on to a copy of
+    /// this address, so a stream attached via an `Addr` will keep the actor alive
+    /// until it ends.
+    fn add_stream<S>(&self, stream: S)
+    where
+        S: Stream + Send + 'static,
+        S::Item: Send + 'static,
+        Self::Actor: StreamHandler<S::Item>,
+    {
+        let addr = self.clone();
+        self.send_fut(async move {
+            let mut stream = Box::pin(stream);
+            loop {
+                let item = stream.next().await;
+                let finished = item.is_none();
+                let (tx, rx) = oneshot::channel();
+                if addr
+                    .send_mut_async(stream_item(addr.priority(), item, tx))
+                    .await
+                    .is_err()
+                    || rx.await.is_err()
+                    || finished
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Returns a future which resolves to the reason the actor stopped, once it
+    /// terminates. If the actor has already terminated, or if this address is
+    /// detached, the future will resolve immediately. Detached addresses report
+    /// an `ExitReason::Normal`.
+    fn termination(&self) -> Termination {
+        Termination::new(self.call_fut(future::pending()), None)
+    }
+
+    // Kills the actor if `termination` resolves to an abnormal reason, unless
+    // the actor terminates first. Used to link actors together. By default
+    // this does nothing, as the actor cannot be killed through this trait.
+    #[doc(hidden)]
+    fn kill_on_failure(&self, termination: impl Future<Output = ExitReason> + Send + 'static) {
+        drop(termination);
+    }
+}
+
+fn stream_item<T: StreamHandler<I> + ?Sized, I: Send + 'static>(
+    priority: usize,
+    item: Option<I>,
+    done: oneshot::Sender<()>,
+) -> MutItem<T> {
+    MutItem::<T>::new(
+        priority,
+        Box::new(move |x, ctx| {
+            async move {
+                let res = crate::hidden::run_method(&ctx, "handle", async {
+                    match item {
+                        Some(item) => x.handle(item).await,
+                        None => x.finished().await,
+                    }
+                })
+                .await;
+                let reason = match res.and_then(|res| res) {
+                    Ok(_) => None,
+                    Err(e) => crate::hidden::handle_error(x, &ctx, e).await,
+                };
+                let _ = done.send(());
+                reason
+            }
+            .boxed()
+        }),
+    )
+}
+
+/// A point in time, along with the clock used to tell whether it has passed.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct Deadline {
+    at: Instant,
+    now: Arc<dyn Fn() -> Instant + Send + Sync>,
+}
+
+impl Debug for Deadline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Deadline").field("at", &self.at).finish()
+    }
+}
+
+impl Deadline {
+    fn new<R: SupportsTimers + Send + Sync + 'static>(runtime: R, at: Instant) -> Self {
+        Self {
+            at,
+            now: Arc::new(move || runtime.now()),
+        }
+    }
+    // The time left until the deadline passes, according to its clock.
+    fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since((self.now)())
+    }
+    fn has_passed(&self) -> bool {
+        (self.now)() >= self.at
+    }
+}
+
+/// An address which attaches a deadline to every message sent through it.
+/// Created using `AddrLike::with_deadline`.
+#[derive(Debug, Clone)]
+pub struct WithDeadline<A> {
+    addr: A,
+    deadline: Deadline,
+}
+
+impl<A: AddrLike> AsAddr for WithDeadline<A> {
+    type Addr = Self;
+    fn as_addr(&self) -> &Self::Addr {
+        self
+    }
+}
+
+impl<A: AddrLike> AddrLike for WithDeadline<A> {
+    type Actor = A::Actor;
+
+    #[doc(hidden)]
+    fn send_mut(&self, item: MutItem<Self::Actor>) -> Result<(), SendError> {
+        self.addr.send_mut(item)
+    }
+
+    #[doc(hidden)]
+    fn send_mut_async(
+        &self,
+        item: MutItem<Self::Actor>,
+    ) -> BoxFuture<'static, Result<(), SendError>> {
+        self.addr.send_mut_async(item)
+    }
+
+    fn send_fut(&self, fut: impl Future<Output = ()> + Send + 'static) {
+        self.addr.send_fut(fut)
+    }
+
+    fn termination(&self) -> Termination {
+        self.addr.termination()
+    }
+
+    #[doc(hidden)]
+    fn kill_on_failure(&self, termination: impl Future<Output = ExitReason> + Send + 'static) {
+        self.addr.kill_on_failure(termination)
+    }
+
+    #[doc(hidden)]
+    fn deadline(&self) -> Option<Deadline> {
+        // The deadlines may be measured by different clocks, so keep whichever
+        // passes first.
+        Some(match self.addr.deadline() {
+            Some(deadline) if deadline.remaining() < self.deadline.remaining() => deadline,
+            _ => self.deadline.clone(),
+        })
+    }
+
+    #[doc(hidden)]
+    fn priority(&self) -> usize {
+        self.addr.priority()
+    }
+
+    #[doc(hidden)]
+    fn actor_id(&self) -> Option<ActorId> {
+        self.addr.actor_id()
+    }
+}
+
+/// An address which sends every message through it at a given priority.
+/// Created using `AddrLike::with_priority`.
+#[derive(Debug, Clone)]
+pub struct WithPriority<A> {
+    addr: A,
+    priority: usize,
+}
+
+impl<A: AddrLike> AsAddr for WithPriority<A> {
+    type Addr = Self;
+    fn as_addr(&self) -> &Self::Addr {
+        self
+    }
+}
+
+impl<A: AddrLike> AddrLike for WithPriority<A> {
+    type Actor = A::Actor;
+
+    #[doc(hidden)]
+    fn send_mut(&self, item: MutItem<Self::Actor>) -> Result<(), SendError> {
+        self.addr.send_mut(item)
+    }
+
+    #[doc(hidden)]
+    fn send_mut_async(
+        &self,
+        item: MutItem<Self::Actor>,
+    ) -> BoxFuture<'static, Result<(), SendError>> {
+        self.addr.send_mut_async(item)
+    }
+
+    fn send_fut(&self, fut: impl Future<Output = ()> + Send + 'static) {
+        self.addr.send_fut(fut)
+    }
+
     fn termination(&self) -> Termination {
-        Termination(self.call_fut(future::pending()))
+        self.addr.termination()
+    }
+
+    #[doc(hidden)]
+    fn kill_on_failure(&self, termination: impl Future<Output = ExitReason> + Send + 'static) {
+        self.addr.kill_on_failure(termination)
+    }
+
+    #[doc(hidden)]
+    fn deadline(&self) -> Option<Deadline> {
+        self.addr.deadline()
+    }
+
+    #[doc(hidden)]
+    fn priority(&self) -> usize {
+        self.priority
+    }
+
+    #[doc(hidden)]
+    fn actor_id(&self) -> Option<ActorId> {
+        self.addr.actor_id()
     }
 }
 
@@ -185,6 +1050,90 @@ impl<T: Actor + ?Sized> AsAddr for crate::WeakAddr<T> {
     }
 }
 
+/// Options used when spawning an actor using `Addr::new_with`.
+///
+/// ```ignore
+/// let options = SpawnOptions::new()
+///     .name("worker")
+///     .capacity(16)
+///     .priorities(2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SpawnOptions {
+    name: Option<Arc<str>>,
+    capacity: Option<usize>,
+    priorities: usize,
+    watchdog: Option<Watchdog>,
+    detect_deadlocks: bool,
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        Self {
+            name: None,
+            capacity: None,
+            priorities: 1,
+            watchdog: None,
+            detect_deadlocks: false,
+        }
+    }
+}
+
+impl SpawnOptions {
+    /// Construct the default options: an unnamed actor with an unbounded mailbox
+    /// and a single priority level.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Give the actor a name.
+    ///
+    /// The name is used to tell actors apart when debugging, and does not need
+    /// to be unique. See `Addr::name`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into().into());
+        self
+    }
+    /// Bound the actor's mailbox.
+    ///
+    /// Messages sent with `try_send!(...)` or `try_call!(...)` will be rejected whilst
+    /// `capacity` messages are already queued, and messages sent with `send_async!(...)`
+    /// or `call_async!(...)` will wait for space to become available. Messages sent
+    /// with `send!(...)` or `call!(...)` are dropped, and a warning is logged.
+    ///
+    /// Requests to stop the actor using `Addr::stop` are always queued.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "Mailbox capacity must be non-zero");
+        self.capacity = Some(capacity);
+        self
+    }
+    /// Give the actor's mailbox `levels` priority levels.
+    ///
+    /// Messages can be sent at a higher priority using `AddrLike::with_priority`.
+    /// Priorities range from zero, which is the default, to `levels - 1`.
+    ///
+    /// Panics if `levels` is zero.
+    pub fn priorities(mut self, levels: usize) -> Self {
+        assert!(levels > 0, "Mailbox must have at least one priority level");
+        self.priorities = levels;
+        self
+    }
+    /// Watch the actor's methods using the given watchdog. See the `watchdog`
+    /// module for details.
+    pub fn watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+    /// Enable or disable deadlock detection for calls awaited by the actor's
+    /// methods. Detection is disabled by default. See the `deadlock` module
+    /// for details.
+    pub fn detect_deadlocks(mut self, enabled: bool) -> Self {
+        self.detect_deadlocks = enabled;
+        self
+    }
+}
+
 /// A strong reference to a spawned actor. Actors can be spawned using `Addr::new`.
 ///
 /// Methods can be called on the actor after it has been spawned using the
@@ -193,19 +1142,17 @@ impl<T: Actor + ?Sized> AsAddr for crate::WeakAddr<T> {
 /// Can be converted to the address of a trait-object using the `upcast!(...)`
 /// macro.
 pub struct Addr<T: ?Sized + 'static> {
-    inner: Option<Arc<dyn Any + Send + Sync>>,
-    send_mut: &'static (dyn Fn(&Arc<dyn Any + Send + Sync>, MutItem<T>) + Send + Sync),
-    send_fut: &'static (dyn Fn(&Arc<dyn Any + Send + Sync>, FutItem) + Send + Sync),
+    inner: Option<Arc<ErasedInner>>,
+    send_mut: &'static SendMutFn<T>,
+    send_fut: &'static SendFutFn,
 }
 
 impl<T: ?Sized> Debug for Addr<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{} {{ detached: {} }}",
-            std::any::type_name::<Self>(),
-            self.inner.is_none()
-        )
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .finish()
     }
 }
 
@@ -227,37 +1174,37 @@ impl<T: ?Sized> Default for Addr<T> {
 
 impl<T: ?Sized, U: ?Sized> PartialEq<Addr<U>> for Addr<T> {
     fn eq(&self, rhs: &Addr<U>) -> bool {
-        self.ptr() == rhs.ptr()
+        self.id() == rhs.id()
     }
 }
 
 impl<T: ?Sized, U: ?Sized> PartialEq<WeakAddr<U>> for Addr<T> {
     fn eq(&self, rhs: &WeakAddr<U>) -> bool {
-        self.ptr() == rhs.ptr()
+        self.id() == rhs.id()
     }
 }
 
 impl<T: ?Sized> Eq for Addr<T> {}
 impl<T: ?Sized> Hash for Addr<T> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.ptr().hash(state)
+        self.id().hash(state)
     }
 }
 
 impl<T: ?Sized, U: ?Sized> PartialOrd<Addr<U>> for Addr<T> {
     fn partial_cmp(&self, rhs: &Addr<U>) -> Option<Ordering> {
-        self.ptr().partial_cmp(&rhs.ptr())
+        self.id().partial_cmp(&rhs.id())
     }
 }
 
 impl<T: ?Sized, U: ?Sized> PartialOrd<WeakAddr<U>> for Addr<T> {
     fn partial_cmp(&self, rhs: &WeakAddr<U>) -> Option<Ordering> {
-        self.ptr().partial_cmp(&rhs.ptr())
+        self.id().partial_cmp(&rhs.id())
     }
 }
 impl<T: ?Sized> Ord for Addr<T> {
     fn cmp(&self, rhs: &Addr<T>) -> Ordering {
-        self.ptr().cmp(&rhs.ptr())
+        self.id().cmp(&rhs.id())
     }
 }
 
@@ -265,10 +1212,23 @@ impl<T: Actor + ?Sized> AddrLike for Addr<T> {
     type Actor = T;
 
     #[doc(hidden)]
-    fn send_mut(&self, item: MutItem<Self::Actor>) {
-        if let Some(inner) = &self.inner {
-            (self.send_mut)(inner, item);
-        }
+    fn send_mut(&self, item: MutItem<Self::Actor>) -> Result<(), SendError> {
+        let inner = self.inner.as_ref().ok_or(SendError::Disconnected)?;
+        inner.shared.mailbox.push()?;
+        send_queued(self.send_mut, inner, item)
+    }
+
+    #[doc(hidden)]
+    fn send_mut_async(
+        &self,
+        item: MutItem<Self::Actor>,
+    ) -> BoxFuture<'static, Result<(), SendError>> {
+        let addr = self.clone();
+        FutureExt::boxed(async move {
+            let inner = addr.inner.as_ref().ok_or(SendError::Disconnected)?;
+            inner.shared.mailbox.push_async().await?;
+            send_queued(addr.send_mut, inner, item)
+        })
     }
 
     fn send_fut(&self, fut: impl Future<Output = ()> + Send + 'static) {
@@ -276,36 +1236,122 @@ impl<T: Actor + ?Sized> AddrLike for Addr<T> {
             (self.send_fut)(inner, FutureExt::boxed(fut));
         }
     }
+
+    fn termination(&self) -> Termination {
+        Termination::new(
+            self.call_fut(future::pending()),
+            self.inner
+                .as_ref()
+                .map(|inner| inner.shared.identity.clone()),
+        )
+    }
+    #[doc(hidden)]
+    fn kill_on_failure(&self, termination: impl Future<Output = ExitReason> + Send + 'static) {
+        // Only hold a weak reference, so that the actor isn't kept alive.
+        self.downgrade().kill_on_failure(termination);
+    }
+    #[doc(hidden)]
+    fn actor_id(&self) -> Option<ActorId> {
+        self.id()
+    }
 }
 
 impl<T: Actor> Addr<T> {
     /// Spawn an actor using the given spawner. If successful returns the address of the actor.
     pub fn new<S: Spawn + ?Sized>(spawner: &S, value: T) -> Result<Self, SpawnError> {
-        let (mtx, mrx) = mpsc::unbounded();
+        Self::new_with(spawner, value, SpawnOptions::default())
+    }
+    /// Spawn an actor with the given options using the given spawner. If successful
+    /// returns the address of the actor.
+    ///
+    /// ```ignore
+    /// let options = SpawnOptions::new().name("db").capacity(100);
+    /// let addr = Addr::new_with(&Runtime, Database::default(), options)?;
+    /// ```
+    pub fn new_with<S: Spawn + ?Sized>(
+        spawner: &S,
+        value: T,
+        options: SpawnOptions,
+    ) -> Result<Self, SpawnError> {
+        Self::new_internal(spawner, value, options, None)
+    }
+    // Spawn an actor which may be restarted in place when it fails.
+    pub(crate) fn new_restartable<S: Spawn + ?Sized>(
+        spawner: &S,
+        value: T,
+        options: SpawnOptions,
+        restart: RestartFn<T>,
+    ) -> Result<Self, SpawnError> {
+        Self::new_internal(spawner, value, options, Some(restart))
+    }
+    fn new_internal<S: Spawn + ?Sized>(
+        spawner: &S,
+        value: T,
+        options: SpawnOptions,
+        restart: Option<RestartFn<T>>,
+    ) -> Result<Self, SpawnError> {
+        let SpawnOptions {
+            name,
+            capacity,
+            priorities,
+            watchdog,
+            detect_deadlocks,
+        } = options;
+        let (ktx, krx) = oneshot::channel();
+        let shared = Arc::new(Shared {
+            identity: Arc::new(Identity {
+                id: ActorId::next(),
+                name,
+                exit_reason: Mutex::new(None),
+            }),
+            actor: std::any::type_name::<T>(),
+            mailbox: Arc::new(Mailbox::new(capacity)),
+            stats: Stats::default(),
+            watchdog,
+            detect_deadlocks,
+            kill: Mutex::new(Some(ktx)),
+            monitors: Mutex::default(),
+        });
+        let (mtx, mrx): (Vec<_>, Vec<_>) = (0..priorities).map(|_| mpsc::unbounded()).unzip();
         let (ftx, frx) = mpsc::unbounded();
-        spawner.spawn(mutex_task(value, mrx, frx))?;
+        let mrx = PriorityReceiver::new(mrx);
         let addr = Self {
             inner: Some(Arc::new(AddrInner {
-                mut_channel: mtx,
-                fut_channel: ftx,
+                shared: shared.clone(),
+                channels: Channels {
+                    mut_channels: mtx,
+                    fut_channel: ftx,
+                },
             })),
-            send_mut: &AddrInner::<T>::send_mut,
-            send_fut: &AddrInner::<T>::send_fut,
+            send_mut: &Channels::<T>::send_mut,
+            send_fut: &Channels::<T>::send_fut,
         };
+        let ctx = ActorContext::new(addr.downgrade(), &shared);
+        spawner.spawn(mutex_task(value, ctx, shared, krx, mrx, frx, restart))?;
 
-        // Tell the actor its own address
-        send!(addr.started(addr.clone()));
+        // Tell the actor its own address. This must not be dropped, even if the
+        // mailbox has a small capacity.
+        let started = {
+            let (addr, this) = (&addr, addr.clone());
+            crate::__impl_send!(@send_item addr=addr ctx=[] moved=[this] method=started)
+        };
+        send_unbounded(addr.send_mut, addr.inner.as_ref().unwrap(), started);
 
         Ok(addr)
     }
     #[doc(hidden)]
-    pub fn upcast<U: ?Sized + Send + 'static, F: Fn(&mut T) -> &mut U + Copy + Send + 'static>(
+    pub fn upcast<
+        U: ?Sized + Send + 'static,
+        F: Fn(&mut T) -> &mut U + Copy + Send + 'static,
+        G: Fn(&T) -> &U + Copy + Send + 'static,
+    >(
         self,
         _f: F,
+        _g: G,
     ) -> Addr<U> {
         Addr {
             inner: self.inner,
-            send_mut: &AddrInner::<T>::send_mut_upcasted::<U, F>,
+            send_mut: &Channels::<T>::send_mut_upcasted::<U, F, G>,
             send_fut: self.send_fut,
         }
     }
@@ -319,19 +1365,69 @@ impl<T: ?Sized> Addr<T> {
             send_fut: &send_unreachable,
         }
     }
-    fn ptr(&self) -> *const () {
+    /// Gracefully stop the actor. Messages which are already queued will be
+    /// processed before the actor stops, and the actor will report an
+    /// `ExitReason::Normal`.
+    ///
+    /// The actor may refuse to stop by returning `false` from `Actor::stopping`.
+    pub fn stop(&self)
+    where
+        T: Actor,
+    {
         if let Some(inner) = &self.inner {
-            Arc::as_ptr(inner) as *const ()
-        } else {
-            ptr::null()
+            send_unbounded(self.send_mut, inner, stop_item());
         }
     }
+    /// Stop the actor immediately. Any method which is currently running will be
+    /// cancelled, queued messages will be dropped, and the actor will report an
+    /// `ExitReason::Killed`.
+    pub fn kill(&self) {
+        if let Some(inner) = &self.inner {
+            inner.shared.kill();
+        }
+    }
+    // The number of messages waiting to be processed by the actor.
+    pub(crate) fn queue_len(&self) -> usize {
+        self.inner
+            .as_ref()
+            .map_or(0, |inner| inner.shared.mailbox.len())
+    }
+    // Returns `false` once the actor has terminated, or if the address is detached.
+    pub(crate) fn is_alive(&self) -> bool {
+        self.inner
+            .as_ref()
+            .is_some_and(|inner| inner.shared.exit_reason().is_none())
+    }
+    /// The unique id of the actor, or `None` if this address is detached.
+    pub fn id(&self) -> Option<ActorId> {
+        self.inner.as_ref().map(|inner| inner.shared.identity.id)
+    }
+    /// The name the actor was given when it was spawned, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.as_ref()?.shared.identity.name.as_deref()
+    }
+    /// Returns a handle which can be used to inspect the state of the actor.
+    pub fn stats(&self) -> ActorStats {
+        ActorStats::new(
+            self.inner
+                .as_ref()
+                .map_or_else(Weak::new, |inner| Arc::downgrade(&inner.shared)),
+        )
+    }
 }
 impl<T: ?Sized + Send + 'static> Addr<T> {
     /// Downgrade to a weak reference, which does not try to keep the actor alive.
     pub fn downgrade(&self) -> WeakAddr<T> {
         WeakAddr {
             inner: self.inner.as_ref().map(Arc::downgrade),
+            shared: self
+                .inner
+                .as_ref()
+                .map_or_else(Weak::new, |inner| Arc::downgrade(&inner.shared)),
+            identity: self
+                .inner
+                .as_ref()
+                .map(|inner| inner.shared.identity.clone()),
             send_mut: self.send_mut,
             send_fut: self.send_fut,
         }
@@ -342,10 +1438,10 @@ impl<T: ?Sized + Send + 'static> Addr<T> {
     /// unsuccessful if the address has become detached.
     pub fn downcast<U: Send + 'static>(self) -> Result<Addr<U>, Addr<T>> {
         if let Some(inner) = &self.inner {
-            if inner.is::<AddrInner<U>>() {
+            if inner.channels.is::<Channels<U>>() {
                 Ok(Addr {
                     inner: self.inner,
-                    send_mut: &AddrInner::<U>::send_mut,
+                    send_mut: &Channels::<U>::send_mut,
                     send_fut: self.send_fut,
                 })
             } else {
@@ -365,15 +1461,21 @@ impl<T: ?Sized + Send + 'static> Addr<T> {
 /// Can be converted to the address of a trait-object using the `upcast!(...)`
 /// macro.
 pub struct WeakAddr<T: ?Sized + 'static> {
-    inner: Option<Weak<dyn Any + Send + Sync>>,
-    send_mut: &'static (dyn Fn(&Arc<dyn Any + Send + Sync>, MutItem<T>) + Send + Sync),
-    send_fut: &'static (dyn Fn(&Arc<dyn Any + Send + Sync>, FutItem) + Send + Sync),
+    inner: Option<Weak<ErasedInner>>,
+    // Outlives the actor's task, unlike `inner`, so that the actor can still
+    // be killed or inspected once all of its strong addresses are dropped.
+    shared: Weak<Shared>,
+    identity: Option<Arc<Identity>>,
+    send_mut: &'static SendMutFn<T>,
+    send_fut: &'static SendFutFn,
 }
 
 impl<T: ?Sized> Clone for WeakAddr<T> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            shared: self.shared.clone(),
+            identity: self.identity.clone(),
             send_mut: self.send_mut,
             send_fut: self.send_fut,
         }
@@ -382,7 +1484,10 @@ impl<T: ?Sized> Clone for WeakAddr<T> {
 
 impl<T: ?Sized> Debug for WeakAddr<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {{..}}", std::any::type_name::<Self>())
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .finish()
     }
 }
 
@@ -394,37 +1499,37 @@ impl<T: ?Sized> Default for WeakAddr<T> {
 
 impl<T: ?Sized, U: ?Sized> PartialEq<Addr<U>> for WeakAddr<T> {
     fn eq(&self, rhs: &Addr<U>) -> bool {
-        self.ptr() == rhs.ptr()
+        self.id() == rhs.id()
     }
 }
 
 impl<T: ?Sized, U: ?Sized> PartialEq<WeakAddr<U>> for WeakAddr<T> {
     fn eq(&self, rhs: &WeakAddr<U>) -> bool {
-        self.ptr() == rhs.ptr()
+        self.id() == rhs.id()
     }
 }
 
 impl<T: ?Sized> Eq for WeakAddr<T> {}
 impl<T: ?Sized> Hash for WeakAddr<T> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.ptr().hash(state)
+        self.id().hash(state)
     }
 }
 
 impl<T: ?Sized, U: ?Sized> PartialOrd<Addr<U>> for WeakAddr<T> {
     fn partial_cmp(&self, rhs: &Addr<U>) -> Option<Ordering> {
-        self.ptr().partial_cmp(&rhs.ptr())
+        self.id().partial_cmp(&rhs.id())
     }
 }
 
 impl<T: ?Sized, U: ?Sized> PartialOrd<WeakAddr<U>> for WeakAddr<T> {
     fn partial_cmp(&self, rhs: &WeakAddr<U>) -> Option<Ordering> {
-        self.ptr().partial_cmp(&rhs.ptr())
+        self.id().partial_cmp(&rhs.id())
     }
 }
 impl<T: ?Sized> Ord for WeakAddr<T> {
     fn cmp(&self, rhs: &WeakAddr<T>) -> Ordering {
-        self.ptr().cmp(&rhs.ptr())
+        self.id().cmp(&rhs.id())
     }
 }
 
@@ -436,10 +1541,25 @@ impl<T: Actor + ?Sized> AddrLike for WeakAddr<T> {
     type Actor = T;
 
     #[doc(hidden)]
-    fn send_mut(&self, item: MutItem<Self::Actor>) {
-        if let Some(inner) = upgrade_weak(&self.inner) {
-            (self.send_mut)(&inner, item);
-        }
+    fn send_mut(&self, item: MutItem<Self::Actor>) -> Result<(), SendError> {
+        let inner = upgrade_weak(&self.inner).ok_or(SendError::Disconnected)?;
+        inner.shared.mailbox.push()?;
+        send_queued(self.send_mut, &inner, item)
+    }
+
+    #[doc(hidden)]
+    fn send_mut_async(
+        &self,
+        item: MutItem<Self::Actor>,
+    ) -> BoxFuture<'static, Result<(), SendError>> {
+        // Avoid keeping the actor alive whilst waiting for space.
+        let addr = self.clone();
+        let mailbox = upgrade_weak(&self.inner).map(|inner| inner.shared.mailbox.clone());
+        FutureExt::boxed(async move {
+            mailbox.ok_or(SendError::Disconnected)?.push_async().await?;
+            let inner = upgrade_weak(&addr.inner).ok_or(SendError::Disconnected)?;
+            send_queued(addr.send_mut, &inner, item)
+        })
     }
 
     fn send_fut(&self, fut: impl Future<Output = ()> + Send + 'static) {
@@ -447,6 +1567,23 @@ impl<T: Actor + ?Sized> AddrLike for WeakAddr<T> {
             (self.send_fut)(&inner, FutureExt::boxed(fut));
         }
     }
+
+    fn termination(&self) -> Termination {
+        Termination::new(self.call_fut(future::pending()), self.identity.clone())
+    }
+    #[doc(hidden)]
+    fn kill_on_failure(&self, termination: impl Future<Output = ExitReason> + Send + 'static) {
+        let this = self.clone();
+        self.send_fut(async move {
+            if termination.await.is_abnormal() {
+                this.kill();
+            }
+        });
+    }
+    #[doc(hidden)]
+    fn actor_id(&self) -> Option<ActorId> {
+        self.id()
+    }
 }
 
 impl<T: ?Sized> WeakAddr<T> {
@@ -454,29 +1591,65 @@ impl<T: ?Sized> WeakAddr<T> {
     pub fn detached() -> Self {
         Self {
             inner: None,
+            shared: Weak::new(),
+            identity: None,
             send_mut: &send_unreachable,
             send_fut: &send_unreachable,
         }
     }
-    // TODO: Replace this with an implementation using `Weak::as_ptr` once support for
-    // unsized values hits stable.
-    fn ptr(&self) -> *const () {
+    /// Gracefully stop the actor. Equivalent to `Addr::stop`.
+    pub fn stop(&self)
+    where
+        T: Actor,
+    {
         if let Some(inner) = upgrade_weak(&self.inner) {
-            Arc::as_ptr(&inner) as *const ()
-        } else {
-            ptr::null()
+            send_unbounded(self.send_mut, &inner, stop_item());
+        }
+    }
+    /// Stop the actor immediately. Equivalent to `Addr::kill`.
+    pub fn kill(&self) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.kill();
         }
     }
+    // Returns `false` once the actor has terminated, or if the address is detached.
+    pub(crate) fn is_alive(&self) -> bool {
+        upgrade_weak(&self.inner).is_some()
+            && self
+                .identity
+                .as_ref()
+                .is_some_and(|identity| identity.exit_reason().is_none())
+    }
+    /// The unique id of the actor, or `None` if this address is detached. The
+    /// id remains the same after the actor has terminated.
+    pub fn id(&self) -> Option<ActorId> {
+        self.identity.as_ref().map(|identity| identity.id)
+    }
+    /// The name the actor was given when it was spawned, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.identity.as_ref()?.name.as_deref()
+    }
+    /// Returns a handle which can be used to inspect the state of the actor.
+    pub fn stats(&self) -> ActorStats {
+        ActorStats::new(self.shared.clone())
+    }
 }
 impl<T: Send + 'static> WeakAddr<T> {
     #[doc(hidden)]
-    pub fn upcast<U: ?Sized + Send + 'static, F: Fn(&mut T) -> &mut U + Copy + Send + 'static>(
+    pub fn upcast<
+        U: ?Sized + Send + 'static,
+        F: Fn(&mut T) -> &mut U + Copy + Send + 'static,
+        G: Fn(&T) -> &U + Copy + Send + 'static,
+    >(
         self,
         _f: F,
+        _g: G,
     ) -> WeakAddr<U> {
         WeakAddr {
             inner: self.inner,
-            send_mut: &AddrInner::<T>::send_mut_upcasted::<U, F>,
+            shared: self.shared,
+            identity: self.identity,
+            send_mut: &Channels::<T>::send_mut_upcasted::<U, F, G>,
             send_fut: self.send_fut,
         }
     }
@@ -485,14 +1658,14 @@ impl<T: ?Sized + Send + 'static> WeakAddr<T> {
     /// Upgrade this to a strong reference. If the actor has already stopped the returned
     /// address will be detached.
     pub fn upgrade(&self) -> Addr<T> {
-        if let Some(inner) = upgrade_weak(&self.inner) {
-            Addr {
-                inner: Some(inner),
-                send_mut: self.send_mut,
-                send_fut: self.send_fut,
-            }
-        } else {
-            Addr::detached()
-        }
+        self.try_upgrade().unwrap_or_else(Addr::detached)
+    }
+    // As `upgrade`, but returns `None` if the actor has already stopped.
+    pub(crate) fn try_upgrade(&self) -> Option<Addr<T>> {
+        Some(Addr {
+            inner: Some(upgrade_weak(&self.inner)?),
+            send_mut: self.send_mut,
+            send_fut: self.send_fut,
+        })
     }
 }
@@ -0,0 +1,237 @@
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::future;
+
+use crate::addr::Shared;
+use crate::timer::{SupportsTimers, Tick, Timer};
+use crate::{send_async, Actor, ActorResult, Addr, AddrLike, ExitReason, Produces, WeakAddr};
+
+/// Identifies a monitor created using `ActorContext::monitor`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MonitorId(u64);
+
+impl MonitorId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Provides an actor with a "terminated" method, that will be called when
+/// an actor it is monitoring terminates.
+///
+/// This trait is defined using the `#[async_trait]` attribute as follows:
+/// ```ignore
+/// #[async_trait]
+/// pub trait Monitor: Actor {
+///     /// Called when a monitored actor terminates.
+///     async fn terminated(&mut self, id: MonitorId, reason: ExitReason) -> ActorResult<()>;
+/// }
+/// ```
+#[async_trait]
+pub trait Monitor: Actor {
+    /// Called when a monitored actor terminates. The `id` is the value which was
+    /// returned when the monitor was created.
+    async fn terminated(&mut self, id: MonitorId, reason: ExitReason) -> ActorResult<()>;
+}
+
+/// Provides an actor with access to its own address from within its methods,
+/// avoiding the need to store the address in a field from `Actor::started`.
+///
+/// Methods opt into receiving the context by taking it as their first argument.
+/// Callers pass `_` in its place, and the context is filled in when the method
+/// runs on the actor:
+///
+/// ```ignore
+/// impl MyActor {
+///     async fn method(&mut self, ctx: &ActorContext<Self>, arg: u32) -> ActorResult<()> {
+///         ctx.set_timeout_for(&mut self.timer, Duration::from_secs(arg));
+///         Produces::ok(())
+///     }
+/// }
+///
+/// send!(addr.method(_, 10));
+/// ```
+///
+/// Methods of traits used as trait-object actors take an
+/// `&ActorContext<dyn Trait>` instead, and are called using an `Addr<dyn Trait>`.
+///
+/// The context can be cloned and moved into futures, such as those spawned
+/// onto the actor using `send_fut`. It does not keep the actor alive.
+pub struct ActorContext<T: ?Sized + 'static> {
+    addr: WeakAddr<T>,
+    shared: Weak<Shared>,
+}
+
+impl<T: ?Sized> Debug for ActorContext<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+impl<T: ?Sized> Clone for ActorContext<T> {
+    fn clone(&self) -> Self {
+        Self {
+            addr: self.addr.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> ActorContext<T> {
+    pub(crate) fn new(addr: WeakAddr<T>, shared: &Arc<Shared>) -> Self {
+        Self {
+            addr,
+            shared: Arc::downgrade(shared),
+        }
+    }
+    // The state of the actor, which is always available whilst its methods are running.
+    pub(crate) fn shared(&self) -> Option<Arc<Shared>> {
+        self.shared.upgrade()
+    }
+    // Describes the actor, for use in trace output.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn describe(&self) -> String {
+        let name = self.addr.name().unwrap_or_default();
+        match self.addr.id() {
+            Some(id) => format!("{}{}", name, id),
+            None => name.into(),
+        }
+    }
+}
+
+impl<T: Send + 'static> ActorContext<T> {
+    // Converts the context for use by methods of a trait-object actor.
+    pub(crate) fn upcast<
+        U: ?Sized + Send + 'static,
+        F: Fn(&mut T) -> &mut U + Copy + Send + 'static,
+        G: Fn(&T) -> &U + Copy + Send + 'static,
+    >(
+        self,
+        f: F,
+        g: G,
+    ) -> ActorContext<U> {
+        ActorContext {
+            addr: self.addr.upcast(f, g),
+            shared: self.shared,
+        }
+    }
+}
+
+impl<T: Actor + ?Sized> ActorContext<T> {
+    /// Returns a strong reference to this actor. This will keep the actor alive
+    /// for as long as the address exists.
+    pub fn addr(&self) -> Addr<T> {
+        self.addr.upgrade()
+    }
+
+    /// Returns a weak reference to this actor.
+    pub fn weak_addr(&self) -> WeakAddr<T> {
+        self.addr.clone()
+    }
+
+    /// Gracefully stop this actor. Equivalent to `Addr::stop`.
+    pub fn stop(&self) {
+        self.addr.stop();
+    }
+
+    /// Stop this actor immediately. Equivalent to `Addr::kill`.
+    pub fn kill(&self) {
+        self.addr.kill();
+    }
+
+    /// Spawn a future onto this actor which does not return a value. The
+    /// future does not keep the actor alive.
+    pub fn send_fut(&self, fut: impl Future<Output = ()> + Send + 'static) {
+        self.addr.send_fut(fut);
+    }
+
+    /// Spawn a future onto this actor and provide the means to get back the
+    /// result. Equivalent to `AddrLike::call_fut`.
+    pub fn call_fut<R: Send + 'static>(
+        &self,
+        fut: impl Future<Output = Produces<R>> + Send + 'static,
+    ) -> Produces<R> {
+        self.addr.call_fut(fut)
+    }
+
+    /// Link this actor to another actor. If either actor terminates for any
+    /// reason other than a graceful stop, the other actor is killed, which
+    /// will in turn propagate to any actors linked to it. Linking to a pool
+    /// links this actor to each of the pool's current workers.
+    ///
+    /// The link is removed once either actor terminates.
+    pub fn link<A: AddrLike>(&self, addr: &A) {
+        addr.kill_on_failure(self.addr.termination());
+        self.addr.kill_on_failure(addr.termination());
+    }
+}
+
+impl<T: Monitor + ?Sized> ActorContext<T> {
+    /// Monitor another actor. Once it terminates, `Monitor::terminated` will be
+    /// called on this actor with the returned id and the reason it terminated.
+    /// If the other actor has already terminated, this happens immediately.
+    ///
+    /// The monitor is removed if this actor terminates first.
+    pub fn monitor<A: AddrLike>(&self, addr: &A) -> MonitorId {
+        let id = MonitorId::next();
+        let termination = addr.termination();
+        let this = self.addr.clone();
+        let shared = self.shared.clone();
+        let (watcher, abort) = future::abortable(async move {
+            let reason = termination.await;
+            if let Some(shared) = shared.upgrade() {
+                shared.monitors().remove(&id);
+            }
+            let _ = send_async!(this.terminated(id, reason)).await;
+        });
+        if let Some(shared) = self.shared() {
+            shared.monitors().insert(id, abort);
+        }
+        self.addr.send_fut(async move {
+            let _ = watcher.await;
+        });
+        id
+    }
+
+    /// Remove a monitor created using `monitor`, so that `Monitor::terminated`
+    /// will not be called for it. Returns `false` if the monitored actor has
+    /// already terminated, in which case `Monitor::terminated` may still be
+    /// called, or if the monitor was already removed.
+    pub fn demonitor(&self, id: MonitorId) -> bool {
+        match self
+            .shared()
+            .and_then(|shared| shared.monitors().remove(&id))
+        {
+            Some(abort) => {
+                abort.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T: Tick> ActorContext<T> {
+    /// Configure `timer` to tick this actor once at the specified time.
+    pub fn set_timeout<R: SupportsTimers>(&self, timer: &mut Timer<R>, deadline: Instant) {
+        timer.set_timeout_weak(self.weak_addr(), deadline);
+    }
+
+    /// Configure `timer` to tick this actor once after the specified duration.
+    pub fn set_timeout_for<R: SupportsTimers>(&self, timer: &mut Timer<R>, duration: Duration) {
+        timer.set_timeout_for_weak(self.weak_addr(), duration);
+    }
+
+    /// Configure `timer` to tick this actor repeatedly at the specified interval.
+    pub fn set_interval<R: SupportsTimers>(&self, timer: &mut Timer<R>, interval: Duration) {
+        timer.set_interval_weak(self.weak_addr(), interval);
+    }
+}
@@ -0,0 +1,212 @@
+//! Functionality related to detecting deadlocks between actors.
+//!
+//! An actor which awaits the result of a `call!(...)` from within one of its
+//! methods cannot process any other messages until the call completes. If
+//! two or more actors end up waiting on each other in this way, none of them
+//! will ever make progress.
+//!
+//! For actors spawned with `SpawnOptions::detect_deadlocks`, act-zero keeps
+//! track of which actors are waiting on which other actors. If awaiting a
+//! call would complete a cycle, the cycle is logged as an error, and the call
+//! fails with `ProducesError::Deadlock` so that the caller can recover.
+//!
+//! Only cycles made up entirely of actors with detection enabled are found,
+//! and only calls which are awaited directly by methods taking exclusive
+//! access to an actor are tracked: futures spawned onto an actor, and methods
+//! called using `call_ref!(...)`, do not prevent the actor from making progress.
+//! Detection adds some overhead to every call made by those actors, so is
+//! intended for debugging.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Context, Poll};
+
+use futures::channel::oneshot;
+use futures::future::FutureExt;
+use log::error;
+
+use crate::{ActorId, Produces, ProducesError};
+
+// Calls which actors are currently waiting on, keyed by a unique token.
+static WAITS: Mutex<BTreeMap<u64, Wait>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    // The actor whose method is currently being polled on this thread, if
+    // it has detection enabled.
+    static WAITER: Cell<Option<ActorId>> = const { Cell::new(None) };
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Wait {
+    waiter: ActorId,
+    callee: ActorId,
+    method: &'static str,
+}
+
+fn lock() -> MutexGuard<'static, BTreeMap<u64, Wait>> {
+    WAITS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Records that an actor is waiting on a call, returning a token which must be
+// passed to `unregister` once it stops waiting. If this would complete a cycle,
+// returns a description of the cycle instead.
+fn register(wait: Wait) -> Result<u64, String> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let mut waits = lock();
+    // Search for a path from the callee back to the waiter, remembering how
+    // each actor was reached so that the path can be reported.
+    let mut reached = BTreeMap::new();
+    let mut visited = BTreeSet::new();
+    let mut frontier = vec![wait.callee];
+    while let Some(id) = frontier.pop() {
+        if id == wait.waiter {
+            let mut path = Vec::new();
+            let mut id = wait.waiter;
+            while id != wait.callee {
+                let prev: Wait = reached[&id];
+                path.push(prev);
+                id = prev.waiter;
+            }
+            let mut cycle = format!(
+                "{} is waiting for {}::{}",
+                wait.waiter, wait.callee, wait.method
+            );
+            for prev in path.iter().rev() {
+                let _ = write!(
+                    cycle,
+                    ", which is waiting for {}::{}",
+                    prev.callee, prev.method
+                );
+            }
+            return Err(cycle);
+        }
+        if !visited.insert(id) {
+            continue;
+        }
+        for next in waits.values().filter(|next| next.waiter == id) {
+            reached.entry(next.callee).or_insert(*next);
+            frontier.push(next.callee);
+        }
+    }
+    let token = NEXT.fetch_add(1, Ordering::Relaxed);
+    waits.insert(token, wait);
+    Ok(token)
+}
+
+fn unregister(token: u64) {
+    lock().remove(&token);
+}
+
+// Wraps a method of an actor, so that calls awaited by the method are
+// attributed to that actor. The id is `None` if the actor does not have
+// detection enabled.
+pub(crate) struct Handler<F> {
+    id: Option<ActorId>,
+    fut: F,
+}
+
+impl<F> Handler<F> {
+    pub(crate) fn new(id: Option<ActorId>, fut: F) -> Self {
+        Self { id, fut }
+    }
+}
+
+// Restores the previous waiter, even if the method panics.
+struct Restore(Option<ActorId>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        WAITER.with(|waiter| waiter.set(self.0));
+    }
+}
+
+impl<F: Future + Unpin> Future for Handler<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let _restore = Restore(WAITER.with(|waiter| waiter.replace(self.id)));
+        self.fut.poll_unpin(cx)
+    }
+}
+
+// The result of a call, which records the caller as waiting on the callee
+// whilst it is pending.
+struct Tracked<T> {
+    inner: Produces<T>,
+    callee: ActorId,
+    method: &'static str,
+    token: Option<u64>,
+}
+
+impl<T> Tracked<T> {
+    fn stop_waiting(&mut self) {
+        if let Some(token) = self.token.take() {
+            unregister(token);
+        }
+    }
+}
+
+impl<T> Future for Tracked<T> {
+    type Output = Result<T, ProducesError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let res = self.inner.poll_unpin(cx);
+        if res.is_ready() {
+            self.stop_waiting();
+            return res;
+        }
+        match WAITER.with(Cell::get) {
+            Some(waiter) if self.token.is_none() => {
+                let wait = Wait {
+                    waiter,
+                    callee: self.callee,
+                    method: self.method,
+                };
+                match register(wait) {
+                    Ok(token) => self.token = Some(token),
+                    Err(cycle) => {
+                        error!("Deadlock detected: {}", cycle);
+                        return Poll::Ready(Err(ProducesError::Deadlock));
+                    }
+                }
+            }
+            Some(_) => {}
+            // No longer being awaited by an actor method.
+            None => self.stop_waiting(),
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        self.stop_waiting();
+    }
+}
+
+// Returns the result of a call to `method` on the actor `callee`, tracking it
+// if the call is made by an actor with detection enabled.
+pub(crate) fn track<T: Send + 'static>(
+    callee: Option<ActorId>,
+    method: &'static str,
+    rx: oneshot::Receiver<Produces<T>>,
+) -> Produces<T> {
+    match callee.filter(|_| WAITER.with(Cell::get).is_some()) {
+        Some(callee) => Produces::Boxed(
+            Tracked {
+                inner: Produces::Deferred(rx),
+                callee,
+                method,
+                token: None,
+            }
+            .boxed(),
+        ),
+        None => Produces::Deferred(rx),
+    }
+}
@@ -0,0 +1,209 @@
+//! Functionality related to broadcasting messages to groups of actors.
+//!
+//! A `Group` holds weak references to a set of actors, typically of a
+//! trait-object type such as `WeakAddr<dyn Trait>`, and can be used as
+//! a publish/subscribe topic. Members which have terminated are removed
+//! automatically.
+//!
+//! Methods can be called on every member of a group using the `send_all!(...)`
+//! and `call_all!(...)` macros. The arguments are cloned for each member.
+//!
+//! ```ignore
+//! let group: Group<dyn Listener> = Group::new();
+//! group.join(upcast!(addr.downgrade()));
+//!
+//! send_all!(group.notify(event));
+//! let replies = call_all!(group.query(question)).quorum(2).await;
+//! ```
+
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use crate::timer::SupportsTimers;
+use crate::{Actor, Addr, Produces, SendError, WeakAddr};
+
+/// A set of actors which can be sent messages all at once.
+///
+/// Cloning a group produces another handle to the same set of members.
+pub struct Group<T: ?Sized + 'static> {
+    members: Arc<Mutex<Vec<WeakAddr<T>>>>,
+}
+
+impl<T: ?Sized> Debug for Group<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("members", &*self.lock())
+            .finish()
+    }
+}
+
+impl<T: ?Sized> Group<T> {
+    fn lock(&self) -> MutexGuard<'_, Vec<WeakAddr<T>>> {
+        self.members.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T: ?Sized> Clone for Group<T> {
+    fn clone(&self) -> Self {
+        Self {
+            members: self.members.clone(),
+        }
+    }
+}
+
+impl<T: ?Sized> Default for Group<T> {
+    fn default() -> Self {
+        Self {
+            members: Arc::default(),
+        }
+    }
+}
+
+impl<T: Actor + ?Sized> Group<T> {
+    /// Construct a new, empty group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock_pruned(&self) -> MutexGuard<'_, Vec<WeakAddr<T>>> {
+        let mut members = self.lock();
+        members.retain(WeakAddr::is_alive);
+        members
+    }
+
+    /// Add an actor to the group. Returns `false` if it was already a member.
+    pub fn join(&self, addr: WeakAddr<T>) -> bool {
+        let mut members = self.lock_pruned();
+        if members.contains(&addr) || !addr.is_alive() {
+            false
+        } else {
+            members.push(addr);
+            true
+        }
+    }
+
+    /// Remove an actor from the group. Returns `false` if it was not a member.
+    pub fn leave(&self, addr: &WeakAddr<T>) -> bool {
+        let mut members = self.lock_pruned();
+        let len = members.len();
+        members.retain(|member| member != addr);
+        members.len() != len
+    }
+
+    /// The number of actors in the group.
+    pub fn len(&self) -> usize {
+        self.lock_pruned().len()
+    }
+
+    /// Returns `true` if the group has no members.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The addresses of all actors in the group.
+    pub fn members(&self) -> Vec<Addr<T>> {
+        self.lock_pruned().iter().map(WeakAddr::upgrade).collect()
+    }
+
+    // The lock is not held whilst calling `f`, since it runs user code such as
+    // the `Clone` implementations of the arguments.
+    #[doc(hidden)]
+    pub fn for_each(&self, mut f: impl FnMut(&WeakAddr<T>) -> Result<(), SendError>) {
+        let members = self.lock_pruned().clone();
+        let disconnected: Vec<_> = members
+            .into_iter()
+            .filter(|member| f(member) == Err(SendError::Disconnected))
+            .collect();
+        if !disconnected.is_empty() {
+            self.lock().retain(|member| !disconnected.contains(member));
+        }
+    }
+}
+
+/// Future returned by the `call_all!(...)` macro, which resolves to the values
+/// produced by each member of the group.
+///
+/// By default, this waits for every member to respond. Members which fail to
+/// produce a value are not included in the result.
+#[must_use = "futures do nothing unless polled"]
+pub struct Gather<R> {
+    pending: FuturesUnordered<Produces<R>>,
+    results: Vec<R>,
+    quorum: Option<usize>,
+    timeout: Option<BoxFuture<'static, ()>>,
+}
+
+impl<R> Debug for Gather<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Gather")
+            .field("pending", &self.pending.len())
+            .field("results", &self.results.len())
+            .field("quorum", &self.quorum)
+            .finish()
+    }
+}
+
+impl<R> Gather<R> {
+    #[doc(hidden)]
+    pub fn new(pending: FuturesUnordered<Produces<R>>) -> Self {
+        Self {
+            pending,
+            results: Vec::new(),
+            quorum: None,
+            timeout: None,
+        }
+    }
+
+    /// Resolve as soon as `quorum` values have been produced.
+    pub fn quorum(mut self, quorum: usize) -> Self {
+        self.quorum = Some(quorum);
+        self
+    }
+
+    /// Resolve with the values produced so far once `timeout` has elapsed.
+    pub fn timeout<S: SupportsTimers>(mut self, runtime: &S, timeout: Duration) -> Self {
+        self.timeout = Some(runtime.delay(runtime.now() + timeout).boxed());
+        self
+    }
+}
+
+impl<R> Unpin for Gather<R> {}
+
+impl<R> Future for Gather<R> {
+    type Output = Vec<R>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            if this
+                .quorum
+                .is_some_and(|quorum| this.results.len() >= quorum)
+            {
+                break;
+            }
+            match this.pending.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(value))) => this.results.push(value),
+                Poll::Ready(Some(Err(_))) => {}
+                Poll::Ready(None) => break,
+                Poll::Pending => {
+                    let timed_out = this
+                        .timeout
+                        .as_mut()
+                        .is_some_and(|timeout| timeout.poll_unpin(cx).is_ready());
+                    if timed_out {
+                        break;
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+        Poll::Ready(std::mem::take(&mut this.results))
+    }
+}
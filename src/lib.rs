@@ -58,24 +58,181 @@
 
 mod actor;
 mod addr;
+mod context;
+pub mod deadlock;
+pub mod group;
 mod macros;
+mod mailbox;
+pub mod metrics;
+pub mod pool;
+mod recipient;
+pub mod registry;
 pub mod runtimes;
+mod span;
+mod stats;
+pub mod supervisor;
 pub mod timer;
 mod utils;
+pub mod watchdog;
 
 pub use actor::*;
 pub use addr::*;
-pub use macros::*;
+pub use context::{ActorContext, Monitor, MonitorId};
+pub use mailbox::*;
+pub use recipient::{Recipient, WeakRecipient};
+pub use stats::ActorStats;
 pub use utils::*;
 
 #[doc(hidden)]
 pub mod hidden {
     pub use futures::channel::oneshot;
     pub use futures::future::FutureExt;
+    pub use futures::stream::FuturesUnordered;
 
     #[cfg(feature = "tracing")]
     pub use log::trace;
 
+    // Passes an error to the actor, and decides whether it should stop.
+    pub async fn handle_error<T: crate::Actor + ?Sized>(
+        actor: &mut T,
+        ctx: &crate::ActorContext<T>,
+        error: crate::ActorError,
+    ) -> Option<crate::ExitReason> {
+        if let Some(shared) = ctx.shared() {
+            shared.stats.record_error();
+        }
+        if actor.error(error).await {
+            // The actor has already decided to stop, so it can't veto it here.
+            actor.stopping(crate::ExitReason::Error).await;
+            Some(crate::ExitReason::Error)
+        } else {
+            None
+        }
+    }
+
+    // Runs an actor method, converting any panic into an error.
+    async fn catch_unwind<F: std::future::Future>(f: F) -> Result<F::Output, crate::ActorError> {
+        FutureExt::catch_unwind(std::panic::AssertUnwindSafe(f))
+            .await
+            .map_err(|payload| crate::PanicError::new(payload).into())
+    }
+
+    // Runs an actor method under the actor's watchdog, if it has one, converting
+    // any panic or timeout into an error.
+    pub async fn run_method<T: ?Sized, F: std::future::Future>(
+        ctx: &crate::ActorContext<T>,
+        method: &'static str,
+        f: F,
+    ) -> Result<F::Output, crate::ActorError> {
+        let f = catch_unwind(f);
+        match ctx.shared() {
+            Some(shared) => shared
+                .watch(method, f)
+                .await
+                .unwrap_or_else(|timeout| Err(timeout.into())),
+            None => f.await,
+        }
+    }
+
+    // Reports messages which were dropped because the actor's mailbox is full.
+    pub fn sent<A: crate::AddrLike>(
+        addr: &A,
+        method: &'static str,
+        sent: Result<(), crate::SendError>,
+    ) {
+        if sent == Err(crate::SendError::Full) {
+            log::warn!("Mailbox of {:?} is full, dropping `{}`", addr, method);
+        }
+    }
+
+    // Converts the result of a call into a `Result<T, CallError<E>>`.
+    pub fn call_result<A: crate::AddrLike, T, E>(
+        addr: &A,
+        method: &'static str,
+        sent: Result<(), crate::SendError>,
+        res: crate::Produces<Result<T, E>>,
+    ) -> impl std::future::Future<Output = Result<T, crate::CallError<E>>> {
+        self::sent(addr, method, sent);
+        async move {
+            sent.map_err(|e| match e {
+                crate::SendError::Full => crate::CallError::Full,
+                crate::SendError::Disconnected => crate::CallError::Closed,
+            })?;
+            res.await?.map_err(crate::CallError::Handler)
+        }
+    }
+
+    // Binds a method to an address, producing a `Recipient` or `WeakRecipient`.
+    pub fn recipient<A: crate::recipient::Bind<M, R>, M, R>(
+        addr: &A,
+        method: &'static str,
+        strong: crate::recipient::ItemFn<crate::Addr<A::Actor>, M, R>,
+        weak: crate::recipient::ItemFn<crate::WeakAddr<A::Actor>, M, R>,
+    ) -> A::Output {
+        addr.bind(method, strong, weak)
+    }
+
+    // Pumps the stream returned by an actor method back to the caller.
+    pub fn call_stream<A: crate::AddrLike, T: Send + 'static>(
+        addr: &A,
+        res: crate::Produces<crate::Streaming<T>>,
+    ) -> crate::ProducesStream<T> {
+        crate::ProducesStream::new(addr, res)
+    }
+
+    // Creates the channel used to send the result of a call back to the caller.
+    pub fn reply<T: Send + 'static>(
+    ) -> (crate::addr::Reply<T>, oneshot::Receiver<crate::Produces<T>>) {
+        crate::addr::Reply::new()
+    }
+
+    // Allows a call to be discarded without running, if the caller gives up on it.
+    pub fn with_reply<T: crate::Actor + ?Sized, R: Send + 'static>(
+        item: crate::addr::MutItem<T>,
+        reply: crate::addr::Reply<R>,
+    ) -> crate::addr::MutItem<T> {
+        item.with_reply(reply)
+    }
+
+    // Creates an item to send to an actor at the priority of the given address.
+    // This also fixes the type of the closure.
+    pub fn mut_item<A: crate::AddrLike>(
+        addr: &A,
+        method: &'static str,
+        run: crate::addr::RunFn<A::Actor>,
+    ) -> crate::addr::MutItem<A::Actor> {
+        crate::addr::MutItem::new(addr.priority(), crate::span::instrument(method, run))
+            .with_deadline(addr.deadline())
+            .with_metrics(method_key::<A>(method))
+    }
+
+    // As `mut_item`, but for methods which only require shared access to the actor.
+    pub fn ref_item<A: crate::AddrLike>(
+        addr: &A,
+        method: &'static str,
+        run: crate::addr::RefRunFn<A::Actor>,
+    ) -> crate::addr::MutItem<A::Actor> {
+        crate::addr::MutItem::new_ref(addr.priority(), crate::span::instrument_ref(method, run))
+            .with_deadline(addr.deadline())
+            .with_metrics(method_key::<A>(method))
+    }
+
+    fn method_key<A: crate::AddrLike>(method: &'static str) -> crate::metrics::MethodKey {
+        crate::metrics::MethodKey {
+            actor: std::any::type_name::<A::Actor>(),
+            method,
+        }
+    }
+
+    // The result of a call, which is sent back by the actor once the method completes.
+    pub fn produces<A: crate::AddrLike, T: Send + 'static>(
+        addr: &A,
+        method: &'static str,
+        rx: oneshot::Receiver<crate::Produces<T>>,
+    ) -> crate::Produces<T> {
+        crate::deadlock::track(addr.actor_id(), method, rx)
+    }
+
     #[cfg(not(feature = "tracing"))]
     #[doc(hidden)]
     #[macro_export]
@@ -86,6 +243,12 @@ pub mod hidden {
     #[cfg(not(feature = "tracing"))]
     pub use trace;
 
+    // Describes the actor which is running a method.
+    #[cfg(feature = "tracing")]
+    pub fn current_actor<T: ?Sized>(ctx: &crate::ActorContext<T>) -> String {
+        ctx.describe()
+    }
+
     #[cfg(feature = "tracing")]
     pub fn type_name_of_val<T: ?Sized>(_val: &T) -> tynm::TypeName<'static> {
         tynm::TypeName::new::<&T>()
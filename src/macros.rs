@@ -1,10 +1,15 @@
 #[doc(hidden)]
 #[macro_export]
 macro_rules! __impl_send {
+    (
+        @parse $caller:tt receiver=[$($receiver:tt)*] tokens = [. $method:ident (_ $(, $args:expr)*)]
+    ) => {
+        $crate::__impl_send!(@move_args $caller ctx=[ctx] args=[$($args),*] input=[$($receiver)*, $method])
+    };
     (
         @parse $caller:tt receiver=[$($receiver:tt)*] tokens = [. $method:ident ($($args:expr),*)]
     ) => {
-        $crate::__impl_send!(@move_args $caller args=[$($args),*] input=[$($receiver)*, $method])
+        $crate::__impl_send!(@move_args $caller ctx=[] args=[$($args),*] input=[$($receiver)*, $method])
     };
     (
         @parse $caller:tt receiver=[$($receiver:tt)*] tokens = [$token:tt $($tokens:tt)*]
@@ -12,108 +17,400 @@ macro_rules! __impl_send {
         $crate::__impl_send!(@parse $caller receiver=[$($receiver)* $token] tokens = [$($tokens)*])
     };
     (
-        @move_args $caller:tt args = [] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[] moved=[] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[] moved=[] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0] moved=[arg0] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0] moved=[arg0] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1] moved=[arg0, arg1] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1] moved=[arg0, arg1] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2] moved=[arg0, arg1, arg2] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2] moved=[arg0, arg1, arg2] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2, $arg3] moved=[arg0, arg1, arg2, arg3] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2, $arg3] moved=[arg0, arg1, arg2, arg3] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2, $arg3, $arg4] moved=[arg0, arg1, arg2, arg3, arg4] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2, $arg3, $arg4] moved=[arg0, arg1, arg2, arg3, arg4] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5] moved=[arg0, arg1, arg2, arg3, arg4, arg5] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5] moved=[arg0, arg1, arg2, arg3, arg4, arg5] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6, $arg7] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6, $arg7] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr, $arg8:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr, $arg8:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6, $arg7, $arg8] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6, $arg7, $arg8] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8] input=$input)
     };
     (
-        @move_args $caller:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr, $arg8:expr, $arg9:expr] input = $input:tt
+        @move_args $caller:tt ctx = $ctx:tt args = [$arg0:expr, $arg1:expr, $arg2:expr, $arg3:expr, $arg4:expr, $arg5:expr, $arg6:expr, $arg7:expr, $arg8:expr, $arg9:expr] input = $input:tt
     ) => {
-        $crate::__impl_send!(@$caller args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6, $arg7, $arg8, $arg9] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8, arg9] input=$input)
+        $crate::__impl_send!(@$caller ctx=$ctx args=[$arg0, $arg1, $arg2, $arg3, $arg4, $arg5, $arg6, $arg7, $arg8, $arg9] moved=[arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8, arg9] input=$input)
     };
     (
-        @send args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+        @send_item addr=$addr:ident ctx=[$($ctx:ident)?] moved=[$($moved:ident),*] method=$method:ident
     ) => {
         {
-            $(
-                let $moved = $args;
-            )*
-            let addr = $crate::AsAddr::as_addr(&$addr);
-            let addr2 = addr.clone();
-            $crate::hidden::trace!("send!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
-            $crate::AddrLike::send_mut(addr, Box::new(move |x| {
-                $crate::hidden::trace!("{}::{}(...)", $crate::hidden::type_name_of_val(x).as_display(), stringify!($method));
+            let addr2 = $addr.clone();
+            $crate::hidden::mut_item($addr, stringify!($method), Box::new(move |x, ctx| {
+                $crate::hidden::trace!("{}[{}]::{}(...)", $crate::hidden::type_name_of_val(x).as_display(), $crate::hidden::current_actor(&ctx), stringify!($method));
                 $crate::hidden::FutureExt::boxed(async move {
                     let _addr = addr2;
-                    if let Err(e) = $crate::IntoActorResult::into_actor_result(x.$method($($moved),*).await) {
-                        $crate::Actor::error(x, e).await
+                    $(
+                        let $ctx = &ctx;
+                    )?
+                    let res = $crate::hidden::run_method(&ctx, stringify!($method), async { x.$method($($ctx,)? $($moved),*).await }).await;
+                    if let Err(e) = res.and_then($crate::IntoActorResult::into_actor_result) {
+                        $crate::hidden::handle_error(x, &ctx, e).await
                     } else {
-                        false
+                        None
+                    }
+                })
+            }))
+        }
+    };
+    (
+        @call_item addr=$addr:ident ctx=[$($ctx:ident)?] moved=[$($moved:ident),*] method=$method:ident
+    ) => {
+        {
+            let addr2 = $addr.clone();
+            let (tx, rx) = $crate::hidden::reply();
+            let reply = ::std::clone::Clone::clone(&tx);
+            let item = $crate::hidden::mut_item($addr, stringify!($method), Box::new(move |x, ctx| {
+                $crate::hidden::trace!("{}[{}]::{}(...)", $crate::hidden::type_name_of_val(x).as_display(), $crate::hidden::current_actor(&ctx), stringify!($method));
+                $crate::hidden::FutureExt::boxed(async move {
+                    let _addr = addr2;
+                    $(
+                        let $ctx = &ctx;
+                    )?
+                    match $crate::hidden::run_method(&ctx, stringify!($method), async { x.$method($($ctx,)? $($moved),*).await }).await {
+                        Ok(res) => match $crate::IntoActorResult::into_actor_result(res) {
+                            Ok(x) => {
+                                tx.send(x);
+                                None
+                            }
+                            Err(e) => $crate::hidden::handle_error(x, &ctx, e).await,
+                        },
+                        Err(e) => {
+                            tx.failed(&e);
+                            $crate::hidden::handle_error(x, &ctx, e).await
+                        }
                     }
                 })
             }));
+            (
+                $crate::hidden::with_reply(item, reply),
+                $crate::hidden::produces($addr, stringify!($method), rx),
+            )
         }
     };
     (
-        @call args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+        @send_ref_item addr=$addr:ident ctx=[$($ctx:ident)?] moved=[$($moved:ident),*] method=$method:ident
     ) => {
         {
-            $(
-                let $moved = $args;
-            )*
-            let addr = $crate::AsAddr::as_addr(&$addr);
-            let addr2 = addr.clone();
-            $crate::hidden::trace!("call!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
-            let (tx, rx) = $crate::hidden::oneshot::channel();
-            $crate::AddrLike::send_mut(addr, Box::new(move |x| {
-                $crate::hidden::trace!("{}::{}(...)", $crate::hidden::type_name_of_val(x).as_display(), stringify!($method));
+            let addr2 = $addr.clone();
+            $crate::hidden::ref_item($addr, stringify!($method), Box::new(move |x, ctx| {
+                $crate::hidden::trace!("{}[{}]::{}(...)", $crate::hidden::type_name_of_val(x).as_display(), $crate::hidden::current_actor(&ctx), stringify!($method));
                 $crate::hidden::FutureExt::boxed(async move {
                     let _addr = addr2;
-                    match $crate::IntoActorResult::into_actor_result(x.$method($($moved),*).await) {
-                        Ok(x) => {
-                            let _ = tx.send(x);
-                            false
+                    $(
+                        let $ctx = &ctx;
+                    )?
+                    let res = $crate::hidden::run_method(&ctx, stringify!($method), async { x.$method($($ctx,)? $($moved),*).await }).await;
+                    res.and_then($crate::IntoActorResult::into_actor_result).err()
+                })
+            }))
+        }
+    };
+    (
+        @call_ref_item addr=$addr:ident ctx=[$($ctx:ident)?] moved=[$($moved:ident),*] method=$method:ident
+    ) => {
+        {
+            let addr2 = $addr.clone();
+            let (tx, rx) = $crate::hidden::reply();
+            let reply = ::std::clone::Clone::clone(&tx);
+            let item = $crate::hidden::ref_item($addr, stringify!($method), Box::new(move |x, ctx| {
+                $crate::hidden::trace!("{}[{}]::{}(...)", $crate::hidden::type_name_of_val(x).as_display(), $crate::hidden::current_actor(&ctx), stringify!($method));
+                $crate::hidden::FutureExt::boxed(async move {
+                    let _addr = addr2;
+                    $(
+                        let $ctx = &ctx;
+                    )?
+                    match $crate::hidden::run_method(&ctx, stringify!($method), async { x.$method($($ctx,)? $($moved),*).await }).await {
+                        Ok(res) => match $crate::IntoActorResult::into_actor_result(res) {
+                            Ok(x) => {
+                                tx.send(x);
+                                None
+                            }
+                            Err(e) => Some(e),
+                        },
+                        Err(e) => {
+                            tx.failed(&e);
+                            Some(e)
                         }
-                        Err(e) => $crate::Actor::error(x, e).await,
                     }
                 })
             }));
-            $crate::Produces::Deferred(rx)
+            (
+                $crate::hidden::with_reply(item, reply),
+                $crate::hidden::produces($addr, stringify!($method), rx),
+            )
+        }
+    };
+    (
+        @recipient ctx=[] args=[] moved=[] input=[$addr:expr, $method:ident]
+    ) => {
+        $crate::hidden::recipient(
+            $crate::AsAddr::as_addr(&$addr),
+            stringify!($method),
+            |addr, arg0, reply| if reply {
+                $crate::__impl_send!(@call_item addr=addr ctx=[] moved=[arg0] method=$method)
+            } else {
+                ($crate::__impl_send!(@send_item addr=addr ctx=[] moved=[arg0] method=$method), $crate::Produces::None)
+            },
+            |addr, arg0, reply| if reply {
+                $crate::__impl_send!(@call_item addr=addr ctx=[] moved=[arg0] method=$method)
+            } else {
+                ($crate::__impl_send!(@send_item addr=addr ctx=[] moved=[arg0] method=$method), $crate::Produces::None)
+            },
+        )
+    };
+    (
+        @send ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            // Using `match` keeps any temporaries in `$addr` alive, so that
+            // expressions such as `addr.with_timeout(&runtime, timeout)` can be used.
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("send!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let item = $crate::__impl_send!(@send_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::hidden::sent(addr, stringify!($method), $crate::AddrLike::send_mut(addr, item));
+                }
+            }
+        }
+    };
+    (
+        @try_send ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("try_send!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let item = $crate::__impl_send!(@send_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::AddrLike::send_mut(addr, item)
+                }
+            }
+        }
+    };
+    (
+        @send_async ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("send_async!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let item = $crate::__impl_send!(@send_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::AddrLike::send_mut_async(addr, item)
+                }
+            }
+        }
+    };
+    (
+        @call ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("call!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let (item, res) = $crate::__impl_send!(@call_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::hidden::sent(addr, stringify!($method), $crate::AddrLike::send_mut(addr, item));
+                    res
+                }
+            }
+        }
+    };
+    (
+        @send_ref ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("send_ref!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let item = $crate::__impl_send!(@send_ref_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::hidden::sent(addr, stringify!($method), $crate::AddrLike::send_mut(addr, item));
+                }
+            }
+        }
+    };
+    (
+        @call_ref ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("call_ref!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let (item, res) = $crate::__impl_send!(@call_ref_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::hidden::sent(addr, stringify!($method), $crate::AddrLike::send_mut(addr, item));
+                    res
+                }
+            }
+        }
+    };
+    (
+        @call_stream ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("call_stream!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let (item, res) = $crate::__impl_send!(@call_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::hidden::sent(addr, stringify!($method), $crate::AddrLike::send_mut(addr, item));
+                    $crate::hidden::call_stream(addr, res)
+                }
+            }
+        }
+    };
+    (
+        @send_all ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$group:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match &$group {
+                group => {
+                    $crate::hidden::trace!("send_all!({}::{}(...))", $crate::hidden::type_name_of_val(group).as_display(), stringify!($method));
+                    $crate::group::Group::for_each(group, |addr| {
+                        $(
+                            let $moved = ::std::clone::Clone::clone(&$moved);
+                        )*
+                        let item = $crate::__impl_send!(@send_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                        $crate::AddrLike::send_mut(addr, item)
+                    });
+                }
+            }
+        }
+    };
+    (
+        @call_all ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$group:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match &$group {
+                group => {
+                    $crate::hidden::trace!("call_all!({}::{}(...))", $crate::hidden::type_name_of_val(group).as_display(), stringify!($method));
+                    let pending = $crate::hidden::FuturesUnordered::new();
+                    $crate::group::Group::for_each(group, |addr| {
+                        $(
+                            let $moved = ::std::clone::Clone::clone(&$moved);
+                        )*
+                        let (item, res) = $crate::__impl_send!(@call_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                        $crate::AddrLike::send_mut(addr, item)?;
+                        pending.push(res);
+                        Ok(())
+                    });
+                    $crate::group::Gather::new(pending)
+                }
+            }
+        }
+    };
+    (
+        @call_result ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("call_result!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let (item, res) = $crate::__impl_send!(@call_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::hidden::call_result(addr, stringify!($method), $crate::AddrLike::send_mut(addr, item), res)
+                }
+            }
+        }
+    };
+    (
+        @try_call ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("try_call!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let (item, res) = $crate::__impl_send!(@call_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::AddrLike::send_mut(addr, item).map(move |()| res)
+                }
+            }
+        }
+    };
+    (
+        @call_async ctx=$ctx:tt args=[$($args:expr),*] moved=[$($moved:ident),*] input=[$addr:expr, $method:ident]
+    ) => {
+        {
+            $(
+                let $moved = $args;
+            )*
+            match $crate::AsAddr::as_addr(&$addr) {
+                addr => {
+                    $crate::hidden::trace!("call_async!({}::{}(...))", $crate::hidden::type_name_of_addr(addr).as_display(), stringify!($method));
+                    let (item, res) = $crate::__impl_send!(@call_item addr=addr ctx=$ctx moved=[$($moved),*] method=$method);
+                    $crate::hidden::FutureExt::map(
+                        $crate::AddrLike::send_mut_async(addr, item),
+                        move |sent| sent.map(move |()| res),
+                    )
+                }
+            }
         }
     };
 }
@@ -128,9 +425,16 @@ macro_rules! __impl_send {
 /// - The method must be an inherent method or trait method callable on the
 ///   actor type.
 /// - The method can take either `&self` or `&mut self` as the receiver, but only `&mut self` will
-///   be passed in.
+///   be passed in. Use `send_ref!(...)` to run methods taking `&self` concurrently.
 /// - The method must return a future, with an output that implements `IntoActorResult`.
 /// - The arguments must be `Send + 'static`.
+///
+/// If the method takes an `&ActorContext<Self>` as its first argument, pass `_`
+/// in its place: `send!(addr.method(_, arg1))`.
+///
+/// If the actor has a bounded mailbox which is full, the call is dropped and a
+/// warning is logged. Use `try_send!(...)` or `send_async!(...)` to handle a
+/// full mailbox instead.
 #[macro_export]
 macro_rules! send {
     ($($tokens:tt)*) => {
@@ -145,7 +449,9 @@ macro_rules! send {
 /// call!(addr.method(arg1, arg2))
 /// ```
 ///
-/// The same constraints as for the `send!(...)` macro apply.
+/// The same constraints as for the `send!(...)` macro apply. If the call is
+/// dropped because the actor's mailbox is full, the future resolves to
+/// `ProducesError::Canceled`.
 #[macro_export]
 macro_rules! call {
     ($($tokens:tt)*) => {
@@ -153,6 +459,197 @@ macro_rules! call {
     };
 }
 
+/// Sends a method call to be executed by the actor with shared access to its state.
+///
+/// ```ignore
+/// send_ref!(addr.method(arg1, arg2))
+/// ```
+///
+/// Consecutive calls made with `send_ref!(...)` or `call_ref!(...)` run
+/// concurrently with each other, but never at the same time as any other
+/// method of the actor. Once a call requiring exclusive access is received,
+/// it waits for the running calls to complete, and calls made after it wait
+/// for it to complete.
+///
+/// The method must take `&self` as the receiver, and the actor must be `Sync`.
+/// Errors are passed to `Actor::error` once all of the concurrent calls have
+/// completed. Otherwise the same constraints as for the `send!(...)` macro apply.
+#[macro_export]
+macro_rules! send_ref {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse send_ref receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Sends a method call to be executed by the actor with shared access to its
+/// state, and returns a future that can be awaited to get back the result.
+///
+/// ```ignore
+/// call_ref!(addr.method(arg1, arg2))
+/// ```
+///
+/// The same constraints as for the `send_ref!(...)` macro apply.
+#[macro_export]
+macro_rules! call_ref {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse call_ref receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Sends a method call to be executed by the actor, and returns a future that can
+/// be awaited to get back the result, flattening any error returned to the caller.
+///
+/// ```ignore
+/// call_result!(addr.method(arg1, arg2))
+/// ```
+///
+/// The method must produce a `Result<T, E>`, for example by returning an
+/// `ActorResult<Result<T, E>>`. Errors of type `E` are returned to the caller
+/// without being passed to `Actor::error`, so they will not stop the actor.
+/// The future resolves to a `Result<T, CallError<E>>`.
+///
+/// The same constraints as for the `send!(...)` macro apply.
+#[macro_export]
+macro_rules! call_result {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse call_result receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Binds a method of an actor to its address, producing a handle which can be
+/// used to call that method without naming the type of the actor.
+///
+/// ```ignore
+/// let recipient: Recipient<LogLine> = recipient!(addr.log);
+/// recipient.send(line);
+/// ```
+///
+/// The method must take exactly one argument. If `addr` is an `Addr<T>`,
+/// this returns a `Recipient<M, R>`, where `M` is the type of the argument
+/// and `R` is the type produced by the method. If `addr` is a `WeakAddr<T>`,
+/// this returns a `WeakRecipient<M, R>` instead.
+#[macro_export]
+macro_rules! recipient {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse recipient receiver=[] tokens=[$($tokens)* ()])
+    };
+}
+
+/// Sends a method call to be executed by the actor, and returns a stream of the
+/// values produced by the method.
+///
+/// ```ignore
+/// call_stream!(addr.method(arg1, arg2))
+/// ```
+///
+/// The method must produce a `Streaming<T>`, for example by returning
+/// `Streaming::ok(stream)`. The returned `ProducesStream<T>` yields each value
+/// produced by the stream. Dropping it cancels the stream.
+///
+/// The same constraints as for the `send!(...)` macro apply.
+#[macro_export]
+macro_rules! call_stream {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse call_stream receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Sends a method call to be executed by every member of a `Group`.
+///
+/// ```ignore
+/// send_all!(group.method(arg1, arg2))
+/// ```
+///
+/// The arguments are cloned for each member, so must implement `Clone`.
+/// Members which have terminated are removed from the group.
+#[macro_export]
+macro_rules! send_all {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse send_all receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Sends a method call to be executed by every member of a `Group`, and returns
+/// a `Gather` future which can be awaited to collect the results.
+///
+/// ```ignore
+/// call_all!(group.method(arg1, arg2)).quorum(2)
+/// ```
+///
+/// The same constraints as for the `send_all!(...)` macro apply.
+#[macro_export]
+macro_rules! call_all {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse call_all receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Attempts to send a method call to be executed by the actor, failing if
+/// the actor's mailbox is full.
+///
+/// ```ignore
+/// try_send!(addr.method(arg1, arg2))?
+/// ```
+///
+/// Returns a `Result<(), SendError>`. The same constraints as for the
+/// `send!(...)` macro apply.
+#[macro_export]
+macro_rules! try_send {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse try_send receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Attempts to send a method call to be executed by the actor, failing if
+/// the actor's mailbox is full. On success, returns a future that can be
+/// awaited to get back the result.
+///
+/// ```ignore
+/// try_call!(addr.method(arg1, arg2))?.await
+/// ```
+///
+/// Returns a `Result<Produces<T>, SendError>`. The same constraints as for the
+/// `send!(...)` macro apply.
+#[macro_export]
+macro_rules! try_call {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse try_call receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Sends a method call to be executed by the actor, waiting for space in the
+/// actor's mailbox if necessary.
+///
+/// ```ignore
+/// send_async!(addr.method(arg1, arg2)).await?
+/// ```
+///
+/// Returns a future resolving to a `Result<(), SendError>`. The arguments are
+/// evaluated immediately. The same constraints as for the `send!(...)` macro apply.
+#[macro_export]
+macro_rules! send_async {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse send_async receiver=[] tokens=[$($tokens)*])
+    };
+}
+
+/// Sends a method call to be executed by the actor, waiting for space in the
+/// actor's mailbox if necessary. Once sent, the future resolves to another future
+/// that can be awaited to get back the result.
+///
+/// ```ignore
+/// call_async!(addr.method(arg1, arg2)).await?.await
+/// ```
+///
+/// Returns a future resolving to a `Result<Produces<T>, SendError>`. The arguments
+/// are evaluated immediately. The same constraints as for the `send!(...)` macro apply.
+#[macro_export]
+macro_rules! call_async {
+    ($($tokens:tt)*) => {
+        $crate::__impl_send!(@parse call_async receiver=[] tokens=[$($tokens)*])
+    };
+}
+
 /// Converts an `Addr<T>` or `WeakAddr<T>` to an `Addr<dyn Trait>` or `WeakAddr<dyn Trait>`.
 ///
 /// ```ignore
@@ -161,6 +658,6 @@ macro_rules! call {
 #[macro_export]
 macro_rules! upcast {
     ($x:expr) => {
-        ($x).upcast(|x| x as _)
+        ($x).upcast(|x| x as _, |x| x as _)
     };
 }
@@ -0,0 +1,242 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use futures::channel::mpsc;
+use futures::stream::{FusedStream, Stream, StreamExt};
+
+/// Error returned when a message could not be delivered to an actor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SendError {
+    /// The actor's mailbox is at capacity.
+    Full,
+    /// The actor has stopped, or the address is detached.
+    Disconnected,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendError::Full => f.write_str("Actor mailbox is full"),
+            SendError::Disconnected => f.write_str("Actor is not running"),
+        }
+    }
+}
+
+impl Error for SendError {}
+
+#[derive(Debug, Default)]
+struct MailboxState {
+    len: usize,
+    closed: bool,
+    next_waiter: usize,
+    waiters: VecDeque<(usize, Waker)>,
+}
+
+/// Keeps track of how many messages are queued for an actor, and
+/// parks senders which are waiting for space to become available.
+#[derive(Debug)]
+pub(crate) struct Mailbox {
+    capacity: Option<usize>,
+    state: Mutex<MailboxState>,
+}
+
+impl Mailbox {
+    pub(crate) fn new(capacity: Option<usize>) -> Self {
+        Self {
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+    fn lock(&self) -> MutexGuard<'_, MailboxState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn has_space(&self, state: &MailboxState) -> bool {
+        self.capacity.map_or(true, |capacity| state.len < capacity)
+    }
+    fn notify_one(&self, state: &mut MailboxState) {
+        if self.has_space(state) {
+            if let Some((_, waker)) = state.waiters.pop_front() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// The number of messages which are currently queued.
+    pub(crate) fn len(&self) -> usize {
+        self.lock().len
+    }
+    /// Calls `f` with the number of queued messages, unless the mailbox has
+    /// been closed. The mailbox cannot be closed whilst `f` is running.
+    pub(crate) fn with_len(&self, f: impl FnOnce(usize)) {
+        let state = self.lock();
+        if !state.closed {
+            f(state.len);
+        }
+    }
+    /// Record that a message was queued, failing if there is no space.
+    pub(crate) fn push(&self) -> Result<(), SendError> {
+        let mut state = self.lock();
+        if state.closed {
+            Err(SendError::Disconnected)
+        } else if !state.waiters.is_empty() || !self.has_space(&state) {
+            Err(SendError::Full)
+        } else {
+            state.len += 1;
+            Ok(())
+        }
+    }
+    /// Record that a message was queued, ignoring the capacity. Only used for
+    /// messages which control the actor itself, such as requests to stop.
+    pub(crate) fn push_unbounded(&self) -> Result<(), SendError> {
+        let mut state = self.lock();
+        if state.closed {
+            Err(SendError::Disconnected)
+        } else {
+            state.len += 1;
+            Ok(())
+        }
+    }
+    /// Returns a future which records that a message was queued once
+    /// there is space available.
+    pub(crate) fn push_async(self: &Arc<Self>) -> PushAsync {
+        PushAsync {
+            mailbox: self.clone(),
+            waiter: None,
+            done: false,
+        }
+    }
+    /// Record that a message was taken from the queue.
+    pub(crate) fn pop(&self) {
+        let mut state = self.lock();
+        state.len = state.len.saturating_sub(1);
+        self.notify_one(&mut state);
+    }
+    /// Mark the mailbox as closed, failing any pending senders. Any messages
+    /// still queued will never be processed, so are no longer counted.
+    pub(crate) fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        state.len = 0;
+        for (_, waker) in state.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by `Mailbox::push_async`.
+#[derive(Debug)]
+pub(crate) struct PushAsync {
+    mailbox: Arc<Mailbox>,
+    waiter: Option<usize>,
+    done: bool,
+}
+
+impl Future for PushAsync {
+    type Output = Result<(), SendError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.mailbox.lock();
+        let position = this
+            .waiter
+            .and_then(|id| state.waiters.iter().position(|&(other, _)| other == id));
+
+        if state.closed {
+            this.done = true;
+            return Poll::Ready(Err(SendError::Disconnected));
+        }
+
+        // Senders which have already been notified get priority over any
+        // senders which are still in the queue.
+        let notified = this.waiter.is_some() && position.is_none();
+        let first_in_line = notified || (this.waiter.is_none() && state.waiters.is_empty());
+        if first_in_line && this.mailbox.has_space(&state) {
+            state.len += 1;
+            this.done = true;
+            return Poll::Ready(Ok(()));
+        }
+
+        let waker = cx.waker().clone();
+        match position {
+            Some(index) => state.waiters[index].1 = waker,
+            None => {
+                let id = *this.waiter.get_or_insert_with(|| {
+                    state.next_waiter = state.next_waiter.wrapping_add(1);
+                    state.next_waiter
+                });
+                if notified {
+                    state.waiters.push_front((id, waker));
+                } else {
+                    state.waiters.push_back((id, waker));
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for PushAsync {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        if let Some(id) = self.waiter {
+            let mut state = self.mailbox.lock();
+            if let Some(index) = state.waiters.iter().position(|&(other, _)| other == id) {
+                state.waiters.remove(index);
+            } else {
+                // We were notified but never used the space, so pass
+                // the notification on to the next sender.
+                self.mailbox.notify_one(&mut state);
+            }
+        }
+    }
+}
+
+/// Receives items from several channels, always preferring the channel with
+/// the highest priority. Items from the same channel are received in order.
+#[derive(Debug)]
+pub(crate) struct PriorityReceiver<T> {
+    // Lowest priority first.
+    receivers: Vec<mpsc::UnboundedReceiver<T>>,
+}
+
+impl<T> PriorityReceiver<T> {
+    pub(crate) fn new(receivers: Vec<mpsc::UnboundedReceiver<T>>) -> Self {
+        Self { receivers }
+    }
+}
+
+impl<T> Stream for PriorityReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut terminated = true;
+        for receiver in self.receivers.iter_mut().rev() {
+            if receiver.is_terminated() {
+                continue;
+            }
+            match receiver.poll_next_unpin(cx) {
+                Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                Poll::Ready(None) => {}
+                Poll::Pending => terminated = false,
+            }
+        }
+        if terminated {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> FusedStream for PriorityReceiver<T> {
+    fn is_terminated(&self) -> bool {
+        self.receivers.iter().all(FusedStream::is_terminated)
+    }
+}
@@ -0,0 +1,325 @@
+//! Functionality related to collecting metrics about actors.
+//!
+//! Metrics are only collected once a recorder has been installed using
+//! `set_recorder`. Messages are counted and timed for each combination of
+//! actor type and method, and the queue depth is reported for each running
+//! actor.
+//!
+//! Only messages sent using the macros are counted: futures spawned onto an
+//! actor, and items from streams attached to it, are not. Messages sent via
+//! the address of a trait object are labelled with the trait object type.
+//!
+//! ```ignore
+//! let recorder = Arc::new(PrometheusRecorder::new());
+//! metrics::set_recorder(recorder.clone());
+//! ...
+//! let body = recorder.render();
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::addr::{ActorId, Shared};
+
+static RECORDER: RwLock<Option<Arc<dyn Recorder>>> = RwLock::new(None);
+
+/// Identifies a method of an actor type, which metrics are recorded against.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MethodKey {
+    /// The name of the actor type.
+    pub actor: &'static str,
+    /// The name of the method.
+    pub method: &'static str,
+}
+
+/// Receives metrics as they are collected. All methods do nothing by default.
+///
+/// Methods are called from within actor tasks and from senders, so should
+/// return quickly.
+pub trait Recorder: Send + Sync + 'static {
+    /// A message was sent to an actor. This is recorded whether or not the
+    /// message could be delivered.
+    fn sent(&self, key: MethodKey) {
+        let _ = key;
+    }
+    /// An actor finished handling a message. `wait` is the time the message
+    /// spent queued, and `duration` is the time spent handling it.
+    fn handled(&self, key: MethodKey, wait: Duration, duration: Duration) {
+        let _ = (key, wait, duration);
+    }
+    /// The handler for a message returned an error, or panicked. The message
+    /// is also counted as handled.
+    fn errored(&self, key: MethodKey) {
+        let _ = key;
+    }
+    /// A message was dropped without being handled: either it could not be
+    /// delivered, or the actor stopped before it was run.
+    fn dropped(&self, key: MethodKey) {
+        let _ = key;
+    }
+    /// The number of messages queued for an actor changed.
+    fn queue_depth(&self, actor: &'static str, id: ActorId, depth: usize) {
+        let _ = (actor, id, depth);
+    }
+    /// An actor stopped, so no more metrics will be reported for it.
+    fn stopped(&self, actor: &'static str, id: ActorId) {
+        let _ = (actor, id);
+    }
+}
+
+/// Install the recorder which receives metrics for all actors in the process,
+/// returning the recorder it replaces.
+pub fn set_recorder(recorder: Arc<dyn Recorder>) -> Option<Arc<dyn Recorder>> {
+    RECORDER
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(recorder)
+}
+
+/// Remove the installed recorder, so that metrics are no longer collected.
+/// Messages which were sent whilst it was installed are still reported to it.
+pub fn remove_recorder() -> Option<Arc<dyn Recorder>> {
+    RECORDER.write().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+fn recorder() -> Option<Arc<dyn Recorder>> {
+    RECORDER.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// Carried along with a message from when it is sent until it is handled.
+// If the message is dropped before then, it is counted as dropped.
+pub(crate) struct MessageMetrics {
+    recorder: Arc<dyn Recorder>,
+    key: MethodKey,
+    sent: Instant,
+    done: bool,
+}
+
+impl MessageMetrics {
+    // Returns `None` if no recorder is installed.
+    pub(crate) fn new(key: MethodKey) -> Option<Self> {
+        let recorder = recorder()?;
+        recorder.sent(key);
+        Some(Self {
+            recorder,
+            key,
+            sent: Instant::now(),
+            done: false,
+        })
+    }
+    pub(crate) fn handled(mut self, began: Instant, errored: bool) {
+        self.done = true;
+        self.recorder.handled(
+            self.key,
+            began.saturating_duration_since(self.sent),
+            began.elapsed(),
+        );
+        if errored {
+            self.recorder.errored(self.key);
+        }
+    }
+}
+
+impl Drop for MessageMetrics {
+    fn drop(&mut self) {
+        if !self.done {
+            self.recorder.dropped(self.key);
+        }
+    }
+}
+
+pub(crate) fn record_queue_depth(actor: &'static str, shared: &Shared) {
+    if let Some(recorder) = recorder() {
+        // The mailbox is closed before the actor is reported as stopped, so this
+        // cannot report the queue depth of an actor which has already stopped.
+        shared
+            .mailbox
+            .with_len(|depth| recorder.queue_depth(actor, shared.identity.id, depth));
+    }
+}
+
+pub(crate) fn record_stopped(actor: &'static str, shared: &Shared) {
+    if let Some(recorder) = recorder() {
+        recorder.stopped(actor, shared.identity.id);
+    }
+}
+
+// Upper bounds of the histogram buckets, in seconds.
+const BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0,
+];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let value = value.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Debug, Default)]
+struct MethodMetrics {
+    sent: u64,
+    handled: u64,
+    errored: u64,
+    dropped: u64,
+    wait: Histogram,
+    duration: Histogram,
+}
+
+type Counter = fn(&MethodMetrics) -> u64;
+type HistogramFn = fn(&MethodMetrics) -> &Histogram;
+
+#[derive(Debug, Default)]
+struct State {
+    methods: BTreeMap<MethodKey, MethodMetrics>,
+    queues: BTreeMap<(&'static str, ActorId), usize>,
+}
+
+/// A recorder which accumulates metrics in memory, so that they can be
+/// rendered in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct PrometheusRecorder {
+    state: Mutex<State>,
+}
+
+impl PrometheusRecorder {
+    /// Construct a new recorder with no metrics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn method(&self, key: MethodKey, f: impl FnOnce(&mut MethodMetrics)) {
+        f(self.lock().methods.entry(key).or_default());
+    }
+
+    /// Render the metrics recorded so far in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let state = self.lock();
+        let mut out = String::new();
+        let counters: [(&str, &str, Counter); 4] = [
+            ("sent", "Messages sent to actors.", |m| m.sent),
+            ("handled", "Messages handled by actors.", |m| m.handled),
+            ("errored", "Messages whose handler failed.", |m| m.errored),
+            ("dropped", "Messages dropped without being handled.", |m| {
+                m.dropped
+            }),
+        ];
+        for (name, help, value) in counters {
+            let name = format!("act_zero_messages_{}_total", name);
+            header(&mut out, &name, help, "counter");
+            for (key, metrics) in &state.methods {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels(key), value(metrics));
+            }
+        }
+        let histograms: [(&str, &str, HistogramFn); 2] = [
+            (
+                "act_zero_mailbox_wait_seconds",
+                "Time messages spent queued before being handled.",
+                |m| &m.wait,
+            ),
+            (
+                "act_zero_handler_duration_seconds",
+                "Time spent handling messages.",
+                |m| &m.duration,
+            ),
+        ];
+        for (name, help, histogram) in histograms {
+            header(&mut out, name, help, "histogram");
+            for (key, metrics) in &state.methods {
+                let histogram = histogram(metrics);
+                let labels = labels(key);
+                for (count, bound) in histogram.buckets.iter().zip(BUCKETS) {
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{{{},le=\"{}\"}} {}",
+                        name, labels, bound, count
+                    );
+                }
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"+Inf\"}} {}",
+                    name, labels, histogram.count
+                );
+                let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+                let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+            }
+        }
+        let name = "act_zero_queue_depth";
+        header(&mut out, name, "Messages queued for each actor.", "gauge");
+        for ((actor, id), depth) in &state.queues {
+            let _ = writeln!(
+                out,
+                "{}{{actor=\"{}\",id=\"{}\"}} {}",
+                name,
+                escape(actor),
+                id,
+                depth
+            );
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labels(key: &MethodKey) -> String {
+    format!(
+        "actor=\"{}\",method=\"{}\"",
+        escape(key.actor),
+        escape(key.method)
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Recorder for PrometheusRecorder {
+    fn sent(&self, key: MethodKey) {
+        self.method(key, |m| m.sent += 1);
+    }
+    fn handled(&self, key: MethodKey, wait: Duration, duration: Duration) {
+        self.method(key, |m| {
+            m.handled += 1;
+            m.wait.observe(wait);
+            m.duration.observe(duration);
+        });
+    }
+    fn errored(&self, key: MethodKey) {
+        self.method(key, |m| m.errored += 1);
+    }
+    fn dropped(&self, key: MethodKey) {
+        self.method(key, |m| m.dropped += 1);
+    }
+    fn queue_depth(&self, actor: &'static str, id: ActorId, depth: usize) {
+        self.lock().queues.insert((actor, id), depth);
+    }
+    fn stopped(&self, actor: &'static str, id: ActorId) {
+        self.lock().queues.remove(&(actor, id));
+    }
+}
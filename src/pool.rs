@@ -0,0 +1,335 @@
+//! Functionality related to pools of identical actors.
+//!
+//! A `Pool` spawns a number of instances of an actor, and can be used with
+//! the `send!(...)` and `call!(...)` macros just like an `Addr`. Each message
+//! sent to the pool is delivered to a single worker, chosen according to the
+//! pool's `Routing` strategy.
+//!
+//! ```ignore
+//! let pool = Pool::new(Runtime, 4, Routing::RoundRobin, || Worker::default())?;
+//! let result = call!(pool.process(job)).await?;
+//! ```
+
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use futures::channel::oneshot;
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::task::{Spawn, SpawnError, SpawnExt};
+use log::warn;
+
+use crate::addr::MutItem;
+use crate::utils::splitmix64_atomic;
+use crate::{
+    Actor, Addr, AddrLike, AsAddr, ExitReason, Produces, SendError, SpawnOptions, Termination,
+};
+
+type Factory<T> = Box<dyn FnMut() -> T + Send>;
+
+/// Determines which worker receives each message sent to a pool.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Routing {
+    /// Messages are delivered to each worker in turn.
+    RoundRobin,
+    /// Messages are delivered to a worker chosen at random.
+    Random,
+    /// Messages are delivered to the worker with the fewest queued messages.
+    LeastLoaded,
+    /// Messages are delivered to a worker chosen by hashing a key, which must
+    /// be provided using `Pool::route`. Resizing the pool only reassigns the
+    /// minimum number of keys. Messages sent directly to the pool are not routed
+    /// by key, so are delivered to each worker in turn and a warning is logged.
+    ConsistentHash,
+}
+
+struct PoolInner<T: 'static> {
+    routing: Routing,
+    options: SpawnOptions,
+    workers: RwLock<Vec<Addr<T>>>,
+    next: AtomicUsize,
+    // The state of the random number generator used by `Routing::Random`.
+    random: AtomicU64,
+    // Set once a message has been sent directly to a `Routing::ConsistentHash` pool.
+    warned: AtomicBool,
+    spawner: Box<dyn Spawn + Send + Sync>,
+    factory: Mutex<Factory<T>>,
+}
+
+impl<T> PoolInner<T> {
+    fn read(&self) -> RwLockReadGuard<'_, Vec<Addr<T>>> {
+        self.workers.read().unwrap_or_else(|e| e.into_inner())
+    }
+    fn write(&self) -> RwLockWriteGuard<'_, Vec<Addr<T>>> {
+        self.workers.write().unwrap_or_else(|e| e.into_inner())
+    }
+    fn factory(&self) -> MutexGuard<'_, Factory<T>> {
+        self.factory.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A handle to a pool of identical actors.
+///
+/// Methods can be called on the pool using the `send!(...)` and `call!(...)`
+/// macros, in which case they will be executed by one of the workers. The
+/// workers stay alive for as long as there are handles to the pool, or
+/// strong references to the workers themselves.
+pub struct Pool<T: 'static> {
+    inner: Arc<PoolInner<T>>,
+}
+
+impl<T> Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct(std::any::type_name::<Self>())
+            .field("routing", &self.inner.routing)
+            .field("len", &self.inner.read().len())
+            .finish()
+    }
+}
+
+impl<T> Clone for Pool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Actor> Pool<T> {
+    /// Spawn `size` workers using the given spawner. The factory is called once
+    /// to create each worker, and again whenever the pool is grown.
+    pub fn new<S: Spawn + Send + Sync + 'static>(
+        spawner: S,
+        size: usize,
+        routing: Routing,
+        factory: impl FnMut() -> T + Send + 'static,
+    ) -> Result<Self, SpawnError> {
+        Self::new_with(spawner, size, routing, SpawnOptions::default(), factory)
+    }
+
+    /// Spawn `size` workers with the given options using the given spawner. The
+    /// options apply to every worker, including those added when the pool is grown.
+    pub fn new_with<S: Spawn + Send + Sync + 'static>(
+        spawner: S,
+        size: usize,
+        routing: Routing,
+        options: SpawnOptions,
+        factory: impl FnMut() -> T + Send + 'static,
+    ) -> Result<Self, SpawnError> {
+        let pool = Self {
+            inner: Arc::new(PoolInner {
+                routing,
+                options,
+                workers: RwLock::new(Vec::new()),
+                next: AtomicUsize::new(0),
+                // Seeded differently for each pool, so that pools don't all
+                // choose their workers in the same order.
+                random: AtomicU64::new(RandomState::new().build_hasher().finish()),
+                warned: AtomicBool::new(false),
+                spawner: Box::new(spawner),
+                factory: Mutex::new(Box::new(factory)),
+            }),
+        };
+        pool.resize(size)?;
+        Ok(pool)
+    }
+
+    /// Grow or shrink the pool to contain `size` workers. Workers which are
+    /// removed from the pool are stopped gracefully, so any messages which are
+    /// already queued for them will still be processed.
+    pub fn resize(&self, size: usize) -> Result<(), SpawnError> {
+        let mut factory = self.inner.factory();
+        let mut workers = self.inner.write();
+        while workers.len() < size {
+            workers.push(self.spawn_worker(&mut factory)?);
+        }
+        for worker in workers.drain(size..) {
+            worker.stop();
+        }
+        Ok(())
+    }
+
+    fn spawn_worker(&self, factory: &mut Factory<T>) -> Result<Addr<T>, SpawnError> {
+        Addr::new_with(&*self.inner.spawner, factory(), self.inner.options.clone())
+    }
+
+    // Replaces any workers which have terminated with new ones. Workers which
+    // cannot be replaced are removed from the pool.
+    fn replace_terminated(&self) {
+        let mut factory = self.inner.factory();
+        let mut workers = self.inner.write();
+        let mut i = 0;
+        while i < workers.len() {
+            if workers[i].is_alive() {
+                i += 1;
+            } else if let Ok(worker) = self.spawn_worker(&mut factory) {
+                workers[i] = worker;
+                i += 1;
+            } else {
+                workers.remove(i);
+            }
+        }
+    }
+
+    /// The number of workers in the pool.
+    pub fn len(&self) -> usize {
+        self.inner.read().len()
+    }
+
+    /// Returns `true` if the pool has no workers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The addresses of all workers currently in the pool.
+    pub fn workers(&self) -> Vec<Addr<T>> {
+        self.inner.read().clone()
+    }
+
+    /// Choose a worker based on the given key. The same key will always be
+    /// routed to the same worker unless the pool is resized, in which case
+    /// only the minimum number of keys are moved to a different worker.
+    ///
+    /// Workers which have terminated are replaced before being routed to.
+    /// Returns a detached address if the pool is empty.
+    pub fn route<K: Hash + ?Sized>(&self, key: &K) -> Addr<T> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        let route = || {
+            let workers = self.inner.read();
+            if workers.is_empty() {
+                Addr::detached()
+            } else {
+                workers[jump_hash(hash, workers.len())].clone()
+            }
+        };
+        let worker = route();
+        if worker.is_alive() {
+            return worker;
+        }
+        self.replace_terminated();
+        route()
+    }
+
+    // Chooses the worker to deliver the next message to. If the chosen worker
+    // has terminated, it is replaced first.
+    fn select(&self) -> Option<Addr<T>> {
+        let worker = self.select_any()?;
+        if worker.is_alive() {
+            return Some(worker);
+        }
+        self.replace_terminated();
+        self.select_any()
+    }
+
+    fn select_any(&self) -> Option<Addr<T>> {
+        let workers = self.inner.read();
+        if workers.is_empty() {
+            return None;
+        }
+        let index = match self.inner.routing {
+            Routing::RoundRobin => self.inner.next.fetch_add(1, Ordering::Relaxed) % workers.len(),
+            Routing::ConsistentHash => {
+                if !self.inner.warned.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Message sent directly to {}, which routes by key: use `Pool::route` \
+                         instead",
+                        std::any::type_name::<Self>()
+                    );
+                }
+                self.inner.next.fetch_add(1, Ordering::Relaxed) % workers.len()
+            }
+            Routing::Random => {
+                (splitmix64_atomic(&self.inner.random) % workers.len() as u64) as usize
+            }
+            Routing::LeastLoaded => {
+                // Start from a different worker each time to spread messages
+                // evenly between workers with the same load.
+                let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+                (0..workers.len())
+                    .map(|offset| (start + offset) % workers.len())
+                    .min_by_key(|&index| workers[index].queue_len())
+                    .unwrap()
+            }
+        };
+        Some(workers[index].clone())
+    }
+}
+
+// Jump consistent hash, as described by Lamping and Veach.
+fn jump_hash(mut key: u64, buckets: usize) -> usize {
+    let mut b = -1i64;
+    let mut j = 0i64;
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as usize
+}
+
+impl<T: Actor> AsAddr for Pool<T> {
+    type Addr = Self;
+    fn as_addr(&self) -> &Self::Addr {
+        self
+    }
+}
+
+impl<T: Actor> AddrLike for Pool<T> {
+    type Actor = T;
+
+    #[doc(hidden)]
+    fn send_mut(&self, item: MutItem<Self::Actor>) -> Result<(), SendError> {
+        self.select().ok_or(SendError::Disconnected)?.send_mut(item)
+    }
+
+    #[doc(hidden)]
+    fn send_mut_async(
+        &self,
+        item: MutItem<Self::Actor>,
+    ) -> BoxFuture<'static, Result<(), SendError>> {
+        match self.select() {
+            Some(worker) => worker.send_mut_async(item),
+            None => future::ready(Err(SendError::Disconnected)).boxed(),
+        }
+    }
+
+    fn send_fut(&self, fut: impl Future<Output = ()> + Send + 'static) {
+        if let Some(worker) = self.select() {
+            worker.send_fut(fut);
+        }
+    }
+
+    /// Resolves once all of the workers currently in the pool have terminated.
+    /// The reason is that of the first worker to have stopped abnormally, in the
+    /// order the workers are held by the pool, or `ExitReason::Normal` if none did.
+    /// Resolves immediately with `ExitReason::Error` if the pool's spawner fails.
+    fn termination(&self) -> Termination {
+        let terminations: Vec<_> = self.workers().iter().map(AddrLike::termination).collect();
+        let (tx, rx) = oneshot::channel();
+        let res = self.inner.spawner.spawn(async move {
+            let reasons = future::join_all(terminations).await;
+            let reason = reasons
+                .into_iter()
+                .find(|reason| reason.is_abnormal())
+                .unwrap_or(ExitReason::Normal);
+            let _ = tx.send(Produces::Value(reason));
+        });
+        match res {
+            Ok(()) => Termination::new(Produces::Deferred(rx), None),
+            Err(_) => Termination::new(Produces::Value(ExitReason::Error), None),
+        }
+    }
+
+    #[doc(hidden)]
+    fn kill_on_failure(&self, termination: impl Future<Output = ExitReason> + Send + 'static) {
+        let termination = termination.shared();
+        for worker in self.workers() {
+            worker.kill_on_failure(termination.clone());
+        }
+    }
+}
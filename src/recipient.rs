@@ -0,0 +1,331 @@
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use futures::future::{self, BoxFuture, FutureExt};
+
+use crate::addr::MutItem;
+use crate::{Actor, Addr, AddrLike, Produces, SendError, Termination, WeakAddr};
+
+// Creates the item to send to the actor. The flag indicates whether the
+// caller wants the result.
+pub(crate) type ItemFn<A, M, R> = fn(&A, M, bool) -> (MutItem<<A as AddrLike>::Actor>, Produces<R>);
+
+// Implemented for both kinds of address, so that a recipient can be
+// converted between strong and weak forms.
+trait Target: AddrLike {
+    fn select<M, R>(bound: &Bound<Self, M, R>) -> ItemFn<Self, M, R>;
+    // Returns `None` if the actor has already stopped.
+    fn to_strong(&self) -> Option<Addr<Self::Actor>>;
+    fn to_weak(&self) -> WeakAddr<Self::Actor>;
+}
+
+impl<T: Actor + ?Sized> Target for Addr<T> {
+    fn select<M, R>(bound: &Bound<Self, M, R>) -> ItemFn<Self, M, R> {
+        bound.strong
+    }
+    fn to_strong(&self) -> Option<Addr<T>> {
+        Some(self.clone())
+    }
+    fn to_weak(&self) -> WeakAddr<T> {
+        self.downgrade()
+    }
+}
+
+impl<T: Actor + ?Sized> Target for WeakAddr<T> {
+    fn select<M, R>(bound: &Bound<Self, M, R>) -> ItemFn<Self, M, R> {
+        bound.weak
+    }
+    fn to_strong(&self) -> Option<Addr<T>> {
+        self.try_upgrade()
+    }
+    fn to_weak(&self) -> WeakAddr<T> {
+        self.clone()
+    }
+}
+
+struct Bound<A: AddrLike, M, R> {
+    addr: A,
+    method: &'static str,
+    strong: ItemFn<Addr<A::Actor>, M, R>,
+    weak: ItemFn<WeakAddr<A::Actor>, M, R>,
+}
+
+trait ErasedRecipient<M, R>: Send + Sync {
+    fn send(&self, msg: M);
+    fn call(&self, msg: M) -> Produces<R>;
+    fn try_call(&self, msg: M) -> Result<Produces<R>, SendError>;
+    fn call_async(&self, msg: M) -> BoxFuture<'static, Result<Produces<R>, SendError>>;
+    fn termination(&self) -> Termination;
+    fn to_strong(&self) -> Option<Arc<dyn ErasedRecipient<M, R>>>;
+    fn to_weak(&self) -> Arc<dyn ErasedRecipient<M, R>>;
+    fn addr(&self) -> &dyn Debug;
+}
+
+impl<A: Target, M: 'static, R: Send + 'static> ErasedRecipient<M, R> for Bound<A, M, R> {
+    fn send(&self, msg: M) {
+        let (item, _) = A::select(self)(&self.addr, msg, false);
+        crate::hidden::sent(&self.addr, self.method, self.addr.send_mut(item));
+    }
+    fn call(&self, msg: M) -> Produces<R> {
+        let (item, res) = A::select(self)(&self.addr, msg, true);
+        let sent = self.addr.send_mut(item);
+        crate::hidden::sent(&self.addr, self.method, sent);
+        sent.map_or(Produces::None, |()| res)
+    }
+    fn try_call(&self, msg: M) -> Result<Produces<R>, SendError> {
+        let (item, res) = A::select(self)(&self.addr, msg, true);
+        self.addr.send_mut(item)?;
+        Ok(res)
+    }
+    fn call_async(&self, msg: M) -> BoxFuture<'static, Result<Produces<R>, SendError>> {
+        let (item, res) = A::select(self)(&self.addr, msg, true);
+        self.addr
+            .send_mut_async(item)
+            .map(move |sent| sent.map(|()| res))
+            .boxed()
+    }
+    fn termination(&self) -> Termination {
+        self.addr.termination()
+    }
+    fn to_strong(&self) -> Option<Arc<dyn ErasedRecipient<M, R>>> {
+        Some(Arc::new(Bound {
+            addr: self.addr.to_strong()?,
+            method: self.method,
+            strong: self.strong,
+            weak: self.weak,
+        }))
+    }
+    fn to_weak(&self) -> Arc<dyn ErasedRecipient<M, R>> {
+        Arc::new(Bound {
+            addr: self.addr.to_weak(),
+            method: self.method,
+            strong: self.strong,
+            weak: self.weak,
+        })
+    }
+    fn addr(&self) -> &dyn Debug {
+        &self.addr
+    }
+}
+
+/// Implemented by addresses which can be bound to a method using the
+/// `recipient!(...)` macro.
+#[doc(hidden)]
+pub trait Bind<M, R>: AddrLike {
+    type Output;
+    fn bind(
+        &self,
+        method: &'static str,
+        strong: ItemFn<Addr<Self::Actor>, M, R>,
+        weak: ItemFn<WeakAddr<Self::Actor>, M, R>,
+    ) -> Self::Output;
+}
+
+impl<T: Actor + ?Sized, M: 'static, R: Send + 'static> Bind<M, R> for Addr<T> {
+    type Output = Recipient<M, R>;
+    fn bind(
+        &self,
+        method: &'static str,
+        strong: ItemFn<Addr<T>, M, R>,
+        weak: ItemFn<WeakAddr<T>, M, R>,
+    ) -> Recipient<M, R> {
+        Recipient {
+            inner: Some(Arc::new(Bound {
+                addr: self.clone(),
+                method,
+                strong,
+                weak,
+            })),
+        }
+    }
+}
+
+impl<T: Actor + ?Sized, M: 'static, R: Send + 'static> Bind<M, R> for WeakAddr<T> {
+    type Output = WeakRecipient<M, R>;
+    fn bind(
+        &self,
+        method: &'static str,
+        strong: ItemFn<Addr<T>, M, R>,
+        weak: ItemFn<WeakAddr<T>, M, R>,
+    ) -> WeakRecipient<M, R> {
+        WeakRecipient {
+            inner: Some(Arc::new(Bound {
+                addr: self.clone(),
+                method,
+                strong,
+                weak,
+            })),
+        }
+    }
+}
+
+/// A strong reference to a single method of a spawned actor, which accepts
+/// messages of type `M` and produces values of type `R`.
+///
+/// Unlike an `Addr<T>`, a recipient does not name the type of the actor, so
+/// it can be used to hold "something which accepts a `LogLine`" without
+/// declaring a trait. Recipients can be created using the `recipient!(...)`
+/// macro.
+pub struct Recipient<M: 'static, R: 'static = ()> {
+    inner: Option<Arc<dyn ErasedRecipient<M, R>>>,
+}
+
+impl<M, R> Debug for Recipient<M, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.inner {
+            Some(inner) => f.debug_tuple("Recipient").field(inner.addr()).finish(),
+            None => f.write_str("Recipient { detached: true }"),
+        }
+    }
+}
+
+impl<M, R> Clone for Recipient<M, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M, R: Send> Default for Recipient<M, R> {
+    fn default() -> Self {
+        Self::detached()
+    }
+}
+
+impl<M, R: Send> Recipient<M, R> {
+    /// Create a recipient which does not refer to any actor.
+    pub fn detached() -> Self {
+        Self { inner: None }
+    }
+    /// Send a message to the actor, ignoring the result. Equivalent to `send!(...)`.
+    pub fn send(&self, msg: M) {
+        if let Some(inner) = &self.inner {
+            inner.send(msg);
+        }
+    }
+    /// Send a message to the actor, and return a future which can be awaited to
+    /// get back the result. Equivalent to `call!(...)`.
+    pub fn call(&self, msg: M) -> Produces<R> {
+        call(&self.inner, msg)
+    }
+    /// Attempt to send a message to the actor, failing if its mailbox is full.
+    /// Equivalent to `try_call!(...)`.
+    pub fn try_call(&self, msg: M) -> Result<Produces<R>, SendError> {
+        try_call(&self.inner, msg)
+    }
+    /// Send a message to the actor once there is space in its mailbox.
+    /// Equivalent to `call_async!(...)`.
+    pub fn call_async(&self, msg: M) -> BoxFuture<'static, Result<Produces<R>, SendError>> {
+        call_async(&self.inner, msg)
+    }
+    /// Returns a future which resolves when the actor terminates. Equivalent to
+    /// `AddrLike::termination`.
+    pub fn termination(&self) -> Termination {
+        termination(&self.inner)
+    }
+    /// Downgrade to a weak reference, which does not try to keep the actor alive.
+    pub fn downgrade(&self) -> WeakRecipient<M, R> {
+        WeakRecipient {
+            inner: self.inner.as_ref().map(|inner| inner.to_weak()),
+        }
+    }
+}
+
+/// A weak reference to a single method of a spawned actor. See `Recipient`.
+pub struct WeakRecipient<M: 'static, R: 'static = ()> {
+    inner: Option<Arc<dyn ErasedRecipient<M, R>>>,
+}
+
+impl<M, R> Debug for WeakRecipient<M, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.inner {
+            Some(inner) => f.debug_tuple("WeakRecipient").field(inner.addr()).finish(),
+            None => f.write_str("WeakRecipient { detached: true }"),
+        }
+    }
+}
+
+impl<M, R> Clone for WeakRecipient<M, R> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<M, R: Send> Default for WeakRecipient<M, R> {
+    fn default() -> Self {
+        Self::detached()
+    }
+}
+
+impl<M, R: Send> WeakRecipient<M, R> {
+    /// Create a recipient which does not refer to any actor.
+    pub fn detached() -> Self {
+        Self { inner: None }
+    }
+    /// Send a message to the actor, ignoring the result. Equivalent to `send!(...)`.
+    pub fn send(&self, msg: M) {
+        if let Some(inner) = &self.inner {
+            inner.send(msg);
+        }
+    }
+    /// Send a message to the actor, and return a future which can be awaited to
+    /// get back the result. Equivalent to `call!(...)`.
+    pub fn call(&self, msg: M) -> Produces<R> {
+        call(&self.inner, msg)
+    }
+    /// Attempt to send a message to the actor, failing if its mailbox is full.
+    /// Equivalent to `try_call!(...)`.
+    pub fn try_call(&self, msg: M) -> Result<Produces<R>, SendError> {
+        try_call(&self.inner, msg)
+    }
+    /// Send a message to the actor once there is space in its mailbox.
+    /// Equivalent to `call_async!(...)`.
+    pub fn call_async(&self, msg: M) -> BoxFuture<'static, Result<Produces<R>, SendError>> {
+        call_async(&self.inner, msg)
+    }
+    /// Returns a future which resolves when the actor terminates. Equivalent to
+    /// `AddrLike::termination`.
+    pub fn termination(&self) -> Termination {
+        termination(&self.inner)
+    }
+    /// Upgrade this to a strong reference. If the actor has already stopped the
+    /// returned recipient will be detached.
+    pub fn upgrade(&self) -> Recipient<M, R> {
+        Recipient {
+            inner: self.inner.as_ref().and_then(|inner| inner.to_strong()),
+        }
+    }
+}
+
+type Inner<M, R> = Option<Arc<dyn ErasedRecipient<M, R>>>;
+
+fn call<M, R>(inner: &Inner<M, R>, msg: M) -> Produces<R> {
+    match inner {
+        Some(inner) => inner.call(msg),
+        None => Produces::None,
+    }
+}
+
+fn try_call<M, R>(inner: &Inner<M, R>, msg: M) -> Result<Produces<R>, SendError> {
+    inner.as_ref().ok_or(SendError::Disconnected)?.try_call(msg)
+}
+
+fn call_async<M, R: Send + 'static>(
+    inner: &Inner<M, R>,
+    msg: M,
+) -> BoxFuture<'static, Result<Produces<R>, SendError>> {
+    match inner {
+        Some(inner) => inner.call_async(msg),
+        None => future::ready(Err(SendError::Disconnected)).boxed(),
+    }
+}
+
+fn termination<M, R>(inner: &Inner<M, R>) -> Termination {
+    match inner {
+        Some(inner) => inner.termination(),
+        None => Termination::new(Produces::None, None),
+    }
+}
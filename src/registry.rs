@@ -0,0 +1,175 @@
+//! Functionality related to looking up actors by name.
+//!
+//! A `Registry` is an actor which maps names to actor addresses, so that
+//! components can find each other without having to pass addresses around.
+//! Entries are keyed by both the name and the type of the actor, so the same
+//! name may be registered once as an `Addr<MyActor>` and once as an
+//! `Addr<dyn MyTrait>`.
+//!
+//! The registry only holds weak references, and entries are removed
+//! automatically when the actor they refer to terminates.
+//!
+//! ```ignore
+//! let registry = spawn_actor(Registry::default());
+//! call!(registry.register("db", db_addr.downgrade())).await?;
+//!
+//! let db: Option<Addr<Database>> = call!(registry.lookup("db")).await?;
+//! ```
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use futures::future::{self, AbortHandle};
+use futures::task::Spawn;
+
+use crate::{send_async, Actor, ActorResult, Addr, AddrLike, Produces, SpawnOptions, WeakAddr};
+
+type Key = (String, TypeId);
+
+#[derive(Debug)]
+struct Entry {
+    // Always a `WeakAddr<T>`, where `T` is the type in the key.
+    addr: Box<dyn Any + Send>,
+    generation: u64,
+    // Cancels the future which waits for the actor to terminate.
+    watcher: AbortHandle,
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        self.watcher.abort();
+    }
+}
+
+/// An actor which allows other actors to be looked up by name.
+#[derive(Debug, Default)]
+pub struct Registry {
+    entries: HashMap<Key, Entry>,
+    generation: u64,
+    addr: WeakAddr<Self>,
+}
+
+#[async_trait]
+impl Actor for Registry {
+    async fn started(&mut self, addr: Addr<Self>) -> ActorResult<()> {
+        self.addr = addr.downgrade();
+        Produces::ok(())
+    }
+}
+
+fn key<T: ?Sized + 'static>(name: impl Into<String>) -> Key {
+    (name.into(), TypeId::of::<T>())
+}
+
+impl Registry {
+    fn get<T: Actor + ?Sized>(&self, key: &Key) -> Option<Addr<T>> {
+        let entry = self.entries.get(key)?;
+        entry.addr.downcast_ref::<WeakAddr<T>>()?.try_upgrade()
+    }
+
+    fn insert<T: Actor + ?Sized>(&mut self, key: Key, addr: WeakAddr<T>) {
+        self.generation += 1;
+        let generation = self.generation;
+        let termination = addr.termination();
+        let registry = self.addr.clone();
+        let key2 = key.clone();
+        let (watcher, abort) = future::abortable(async move {
+            termination.await;
+            let _ = send_async!(registry.remove(key2, generation)).await;
+        });
+        self.addr.send_fut(async move {
+            let _ = watcher.await;
+        });
+        self.entries.insert(
+            key,
+            Entry {
+                addr: Box::new(addr),
+                generation,
+                watcher: abort,
+            },
+        );
+    }
+
+    async fn remove(&mut self, key: Key, generation: u64) {
+        if self
+            .entries
+            .get(&key)
+            .is_some_and(|entry| entry.generation == generation)
+        {
+            self.entries.remove(&key);
+        }
+    }
+
+    /// Register an actor under the given name. Returns `false` if another actor
+    /// of the same type is already registered under that name, in which case
+    /// the registry is left unchanged.
+    pub async fn register<T: Actor + ?Sized>(
+        &mut self,
+        name: impl Into<String>,
+        addr: WeakAddr<T>,
+    ) -> ActorResult<bool> {
+        let key = key::<T>(name);
+        if self.get::<T>(&key).is_some() {
+            return Produces::ok(false);
+        }
+        self.insert(key, addr);
+        Produces::ok(true)
+    }
+
+    /// Remove the given actor from under the given name. Returns `false` if
+    /// it was not registered under that name.
+    pub async fn unregister<T: Actor + ?Sized>(
+        &mut self,
+        name: impl Into<String>,
+        addr: WeakAddr<T>,
+    ) -> ActorResult<bool> {
+        let key = key::<T>(name);
+        let found = self.get::<T>(&key).is_some_and(|other| other == addr);
+        if found {
+            self.entries.remove(&key);
+        }
+        Produces::ok(found)
+    }
+
+    /// Look up the actor of type `T` registered under the given name. The type
+    /// cannot be specified using turbofish syntax within `call!(...)`, so it
+    /// must be inferred from the result.
+    pub async fn lookup<T: Actor + ?Sized>(
+        &mut self,
+        name: impl Into<String>,
+    ) -> ActorResult<Option<Addr<T>>> {
+        Produces::ok(self.get(&key::<T>(name)))
+    }
+
+    /// Look up the actor of type `T` registered under the given name. If there
+    /// is no such actor, a new one is created by calling `factory`, spawned using
+    /// `spawner`, and registered under that name.
+    pub async fn get_or_spawn<T: Actor, S: Spawn>(
+        &mut self,
+        name: impl Into<String>,
+        spawner: S,
+        factory: impl FnOnce() -> T,
+    ) -> ActorResult<Addr<T>> {
+        self.get_or_spawn_with(name, spawner, SpawnOptions::default(), factory)
+            .await
+    }
+
+    /// As `get_or_spawn`, but the new actor is spawned with the given options.
+    /// The options are not used if the actor is already registered.
+    pub async fn get_or_spawn_with<T: Actor, S: Spawn>(
+        &mut self,
+        name: impl Into<String>,
+        spawner: S,
+        options: SpawnOptions,
+        factory: impl FnOnce() -> T,
+    ) -> ActorResult<Addr<T>> {
+        let key = key::<T>(name);
+        if let Some(addr) = self.get(&key) {
+            return Produces::ok(addr);
+        }
+        let addr = Addr::new_with(&spawner, factory(), options)?;
+        self.insert(key, addr.downgrade());
+        Produces::ok(addr)
+    }
+}
@@ -20,6 +20,10 @@
 //! not enabled, the `panic` runtime will be re-exported as the default.
 //! This allows library authors to build against the default runtime whilst
 //! remaining runtime agnostic.
+//!
+//! The `panic` and `test` runtimes do not require any features. The `test`
+//! runtime is a deterministic executor with a virtual clock, intended for
+//! testing actors without depending on real time.
 
 #[cfg(feature = "tokio")]
 pub mod tokio;
@@ -28,6 +32,7 @@ pub mod tokio;
 pub mod async_std;
 
 pub mod panic;
+pub mod test;
 
 #[cfg(all(feature = "default-tokio", not(feature = "default-disabled")))]
 pub use self::tokio as default;
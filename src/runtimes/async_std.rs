@@ -61,7 +61,7 @@ mod tests {
     // Tests that .termination() waits for the Actor to be dropped
     #[async_std::test]
     async fn wait_drop_test() {
-        use std::time::{Duration, Instant};
+        use std::time::Duration;
         struct WaitDrop {
             tx: std::sync::mpsc::SyncSender<u32>,
         }
@@ -0,0 +1,684 @@
+//! Deterministic single-threaded runtime for use in tests.
+//!
+//! Futures spawned onto this runtime are only run when the test drives the
+//! runtime, using `Runtime::block_on`, `Runtime::run_until_idle` or
+//! `Runtime::advance`. Time is virtual: it only moves forward when the test
+//! advances it, or when `block_on` has nothing left to do except wait for a
+//! timer. Timer tests therefore complete instantly, and do not depend on how
+//! quickly the machine running them happens to be.
+//!
+//! By default, tasks are run in the order in which they were woken. A runtime
+//! created using `Runtime::with_seed` picks the next task to run at random,
+//! which can be used to explore different interleavings of the same test.
+//! Runs with the same seed always produce the same order.
+//!
+//! ```ignore
+//! let rt = Runtime::new();
+//! let addr = rt.spawn_actor(MyActor::default());
+//! rt.block_on(async {
+//!     call!(addr.wait_for(Duration::from_secs(60))).await
+//! })?;
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures::future::FutureObj;
+use futures::task::{waker, ArcWake, Spawn, SpawnError};
+
+use crate::utils::splitmix64;
+use crate::{timer, Actor, Addr};
+
+/// Alias for a timer based on the test runtime.
+/// Timers are constructed using `Timer::new(runtime.clone())`.
+pub type Timer = timer::Timer<Runtime>;
+
+struct State {
+    now: Instant,
+    tasks: HashMap<u64, FutureObj<'static, ()>>,
+    next_task: u64,
+    // Tasks which have been woken, in the order they were woken.
+    ready: VecDeque<u64>,
+    queued: BTreeSet<u64>,
+    // Wakers for pending delays, ordered by deadline.
+    timers: BTreeMap<(Instant, u64), Waker>,
+    next_timer: u64,
+    // The state of the random number generator, if scheduling is random.
+    rng: Option<u64>,
+}
+
+impl State {
+    fn wake(&mut self, id: u64) {
+        if self.queued.insert(id) {
+            self.ready.push_back(id);
+        }
+    }
+    fn next_ready(&mut self) -> Option<u64> {
+        let index = match &mut self.rng {
+            Some(rng) if !self.ready.is_empty() => {
+                (splitmix64(rng) % self.ready.len() as u64) as usize
+            }
+            _ => 0,
+        };
+        let id = self.ready.remove(index)?;
+        self.queued.remove(&id);
+        Some(id)
+    }
+}
+
+struct Inner {
+    state: Mutex<State>,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+struct TaskWaker {
+    id: u64,
+    inner: Weak<Inner>,
+}
+
+impl ArcWake for TaskWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Some(inner) = arc_self.inner.upgrade() {
+            inner.lock().wake(arc_self.id);
+        }
+    }
+}
+
+/// Type representing a deterministic test runtime. Cloning the runtime
+/// produces another handle to the same executor and clock.
+#[derive(Clone)]
+pub struct Runtime {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.inner.lock();
+        f.debug_struct("Runtime")
+            .field("tasks", &state.tasks.len())
+            .field("ready", &state.ready.len())
+            .field("timers", &state.timers.len())
+            .finish()
+    }
+}
+
+// `Runtime` deliberately does not implement `Default`, so that a timer or
+// actor can't implicitly create a separate runtime which is never driven.
+#[allow(clippy::new_without_default)]
+impl Runtime {
+    /// Construct a new runtime which runs tasks in the order in which they
+    /// were woken. The virtual clock starts at the current time.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    now: Instant::now(),
+                    tasks: HashMap::new(),
+                    next_task: 0,
+                    ready: VecDeque::new(),
+                    queued: BTreeSet::new(),
+                    timers: BTreeMap::new(),
+                    next_timer: 0,
+                    rng: None,
+                }),
+            }),
+        }
+    }
+
+    /// Construct a new runtime which runs tasks in a random order, determined
+    /// by the seed.
+    pub fn with_seed(seed: u64) -> Self {
+        let rt = Self::new();
+        rt.inner.lock().rng = Some(seed);
+        rt
+    }
+
+    /// Spawn an actor onto this runtime, equivalent to `Addr::new`.
+    pub fn spawn_actor<T: Actor>(&self, actor: T) -> Addr<T> {
+        Addr::new(self, actor).unwrap()
+    }
+
+    /// The current time, according to the virtual clock.
+    pub fn now(&self) -> Instant {
+        self.inner.lock().now
+    }
+
+    // Polls the next task which is ready, returning `false` if there are none.
+    fn run_one(&self) -> bool {
+        let (id, mut task) = {
+            let mut state = self.inner.lock();
+            loop {
+                let Some(id) = state.next_ready() else {
+                    return false;
+                };
+                // Tasks which have already completed may still be woken.
+                if let Some(task) = state.tasks.remove(&id) {
+                    break (id, task);
+                }
+            }
+        };
+        let waker = waker(Arc::new(TaskWaker {
+            id,
+            inner: Arc::downgrade(&self.inner),
+        }));
+        if Pin::new(&mut task)
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending()
+        {
+            self.inner.lock().tasks.insert(id, task);
+        }
+        true
+    }
+
+    /// Run tasks until none of them are able to make progress without time
+    /// advancing.
+    pub fn run_until_idle(&self) {
+        while self.run_one() {}
+    }
+
+    // Moves the clock forward to the given time, or to the next timer before
+    // then, waking any timers which have elapsed. Returns `false` if there
+    // were no timers to wake.
+    fn advance_to(&self, deadline: Instant) -> bool {
+        let wakers = {
+            let mut state = self.inner.lock();
+            let next = state.timers.keys().next().map(|&(at, _)| at);
+            let now = match next {
+                Some(at) if at <= deadline => at,
+                _ => deadline,
+            };
+            state.now = state.now.max(now);
+            let pending = state.timers.split_off(&(now, u64::MAX));
+            mem::replace(&mut state.timers, pending)
+        };
+        let woken = !wakers.is_empty();
+        wakers.into_values().for_each(Waker::wake);
+        woken
+    }
+
+    /// Advance the virtual clock by the given duration. Timers are fired in
+    /// order as the clock passes their deadlines, and tasks are run until idle
+    /// after each one.
+    pub fn advance(&self, duration: Duration) {
+        let deadline = self.now() + duration;
+        self.run_until_idle();
+        while self.advance_to(deadline) {
+            self.run_until_idle();
+        }
+    }
+
+    /// Run a future to completion on this runtime, along with any tasks it
+    /// spawns. Whenever all tasks are idle, the clock is advanced to the next
+    /// timer.
+    ///
+    /// Panics if the future cannot complete because no tasks are able to make
+    /// progress and there are no timers remaining.
+    pub fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        futures::pin_mut!(fut);
+        let woken = Arc::new(MainWaker(AtomicBool::new(true)));
+        let waker = waker(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if woken.0.swap(false, Ordering::SeqCst) {
+                if let Poll::Ready(res) = fut.as_mut().poll(&mut cx) {
+                    return res;
+                }
+            } else if !self.run_one() {
+                let next = self.inner.lock().timers.keys().next().map(|&(at, _)| at);
+                match next {
+                    Some(at) => {
+                        self.advance_to(at);
+                    }
+                    None => panic!("Future passed to `block_on` can never complete"),
+                }
+            }
+        }
+    }
+}
+
+// Wakes the future passed to `block_on`, which is not itself a task.
+struct MainWaker(AtomicBool);
+
+impl ArcWake for MainWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Spawn for Runtime {
+    fn spawn_obj(&self, future: FutureObj<'static, ()>) -> Result<(), SpawnError> {
+        let mut state = self.inner.lock();
+        let id = state.next_task;
+        state.next_task += 1;
+        state.tasks.insert(id, future);
+        state.wake(id);
+        Ok(())
+    }
+}
+
+/// Future returned by the test runtime's implementation of `SupportsTimers::delay`.
+/// Completes once the virtual clock reaches the deadline.
+#[derive(Debug)]
+pub struct Delay {
+    inner: Weak<Inner>,
+    deadline: Instant,
+    key: Option<(Instant, u64)>,
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let inner = match self.inner.upgrade() {
+            Some(inner) => inner,
+            None => return Poll::Pending,
+        };
+        let mut state = inner.lock();
+        if state.now >= self.deadline {
+            if let Some(key) = self.key.take() {
+                state.timers.remove(&key);
+            }
+            return Poll::Ready(());
+        }
+        let key = match self.key {
+            Some(key) => key,
+            None => {
+                let key = (self.deadline, state.next_timer);
+                state.next_timer += 1;
+                key
+            }
+        };
+        state.timers.insert(key, cx.waker().clone());
+        drop(state);
+        self.key = Some(key);
+        Poll::Pending
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if let (Some(key), Some(inner)) = (self.key, self.inner.upgrade()) {
+            inner.lock().timers.remove(&key);
+        }
+    }
+}
+
+impl timer::SupportsTimers for Runtime {
+    type Delay = Delay;
+    fn delay(&self, deadline: Instant) -> Self::Delay {
+        Delay {
+            inner: Arc::downgrade(&self.inner),
+            deadline,
+            key: None,
+        }
+    }
+    fn now(&self) -> Instant {
+        Runtime::now(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use futures::channel::oneshot;
+    use futures::task::SpawnExt;
+
+    use super::*;
+    use crate::timer::SupportsTimers;
+    use crate::*;
+
+    struct DebouncedEcho {
+        timer: Timer,
+        response: Option<(&'static str, oneshot::Sender<&'static str>)>,
+    }
+
+    impl Actor for DebouncedEcho {}
+
+    #[async_trait]
+    impl timer::Tick for DebouncedEcho {
+        async fn tick(&mut self) -> ActorResult<()> {
+            if self.timer.tick() {
+                let (msg, tx) = self.response.take().unwrap();
+                let _ = tx.send(msg);
+            }
+            Produces::ok(())
+        }
+    }
+    impl DebouncedEcho {
+        async fn echo(
+            &mut self,
+            ctx: &ActorContext<Self>,
+            msg: &'static str,
+        ) -> ActorResult<oneshot::Receiver<&'static str>> {
+            let (tx, rx) = oneshot::channel();
+            self.response = Some((msg, tx));
+            ctx.set_timeout_for(&mut self.timer, Duration::from_secs(3600));
+            Produces::ok(rx)
+        }
+    }
+
+    fn echo_actor(rt: &Runtime) -> Addr<DebouncedEcho> {
+        rt.spawn_actor(DebouncedEcho {
+            timer: Timer::new(rt.clone()),
+            response: None,
+        })
+    }
+
+    #[test]
+    fn block_on_test() {
+        let rt = Runtime::new();
+        let start = rt.now();
+        let addr = echo_actor(&rt);
+
+        let res = rt.block_on(async {
+            let rx = call!(addr.echo(_, "test")).await.unwrap();
+            rx.await.unwrap()
+        });
+        assert_eq!(res, "test");
+        assert_eq!(rt.now() - start, Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn advance_test() {
+        let rt = Runtime::new();
+        let addr = echo_actor(&rt);
+
+        let mut rx = rt.block_on(call!(addr.echo(_, "test"))).unwrap();
+        rt.advance(Duration::from_secs(3599));
+        assert_eq!(rx.try_recv(), Ok(None));
+        rt.advance(Duration::from_secs(1));
+        assert_eq!(rx.try_recv(), Ok(Some("test")));
+    }
+
+    #[test]
+    #[should_panic(expected = "can never complete")]
+    fn stalled_test() {
+        let rt = Runtime::new();
+        rt.block_on(futures::future::pending::<()>());
+    }
+
+    struct Sleeper(Runtime);
+
+    impl Actor for Sleeper {}
+    impl Sleeper {
+        async fn wait(&mut self, secs: u64) -> ActorResult<u64> {
+            let rt = &self.0;
+            rt.delay(rt.now() + Duration::from_secs(secs)).await;
+            Produces::ok(secs)
+        }
+    }
+
+    #[test]
+    fn deadline_test() {
+        let rt = Runtime::new();
+        let addr = rt.spawn_actor(Sleeper(rt.clone()));
+
+        // Deadlines are measured using the virtual clock.
+        send!(addr.wait(60));
+        let late = call!(addr.with_timeout(&rt, Duration::from_secs(30)).wait(0));
+        let on_time = call!(addr.with_timeout(&rt, Duration::from_secs(90)).wait(0));
+        assert_eq!(rt.block_on(late), Err(ProducesError::TimedOut));
+        assert_eq!(rt.block_on(on_time), Ok(0));
+
+        let other = rt.spawn_actor(Sleeper(rt.clone()));
+        let pending = vec![call!(addr.wait(1)), call!(other.wait(100))];
+        let gather =
+            group::Gather::new(pending.into_iter().collect()).timeout(&rt, Duration::from_secs(10));
+        assert_eq!(rt.block_on(gather), vec![1]);
+    }
+
+    #[test]
+    fn produces_timeout_test() {
+        let rt = Runtime::new();
+        let addr = rt.spawn_actor(Sleeper(rt.clone()));
+
+        let res = call!(addr.wait(60)).timeout(&rt, Duration::from_secs(30));
+        assert_eq!(rt.block_on(res), Err(ProducesError::TimedOut));
+        let res = call!(addr.wait(60)).timeout(&rt, Duration::from_secs(150));
+        assert_eq!(rt.block_on(res), Ok(60));
+
+        // Deadlines are absolute, so are unaffected by time spent before awaiting
+        let deadline = rt.now() + Duration::from_secs(100);
+        rt.advance(Duration::from_secs(80));
+        let res = call!(addr.wait(60)).timeout_at(&rt, deadline);
+        assert_eq!(rt.block_on(res), Err(ProducesError::TimedOut));
+    }
+
+    #[test]
+    fn watchdog_test() {
+        use crate::watchdog::{HandlerTimeout, Watchdog};
+
+        struct Slow {
+            rt: Runtime,
+            timeouts: Vec<&'static str>,
+        }
+
+        #[async_trait]
+        impl Actor for Slow {
+            async fn error(&mut self, error: ActorError) -> bool {
+                match error.downcast::<HandlerTimeout>() {
+                    Ok(timeout) => self.timeouts.push(timeout.method()),
+                    // Error handling is not subject to the watchdog.
+                    Err(_) => {
+                        self.rt
+                            .delay(self.rt.now() + Duration::from_secs(100))
+                            .await;
+                        self.timeouts.push("none");
+                    }
+                }
+                false
+            }
+        }
+        impl Slow {
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+            async fn wait(&mut self, secs: u64) {
+                self.rt
+                    .delay(self.rt.now() + Duration::from_secs(secs))
+                    .await;
+            }
+            async fn patient(&mut self, secs: u64) {
+                self.rt
+                    .delay(self.rt.now() + Duration::from_secs(secs))
+                    .await;
+            }
+            async fn wait_ref(&self, secs: u64) {
+                self.rt
+                    .delay(self.rt.now() + Duration::from_secs(secs))
+                    .await;
+            }
+            async fn timeouts(&mut self) -> ActorResult<Vec<&'static str>> {
+                Produces::ok(self.timeouts.clone())
+            }
+        }
+
+        let rt = Runtime::new();
+        let spawn = |watchdog: Watchdog| {
+            let actor = Slow {
+                rt: rt.clone(),
+                timeouts: Vec::new(),
+            };
+            Addr::new_with(&rt, actor, SpawnOptions::new().watchdog(watchdog)).unwrap()
+        };
+        let watchdog = Watchdog::new(rt.clone(), Duration::from_secs(50))
+            .method("patient", Duration::from_secs(5000))
+            .cancel(true);
+
+        let addr = spawn(watchdog.clone());
+        rt.block_on(call!(addr.wait(0))).unwrap();
+        assert_eq!(
+            rt.block_on(call!(addr.wait(1000))),
+            Err(ProducesError::TimedOut)
+        );
+        assert_eq!(
+            rt.block_on(call_ref!(addr.wait_ref(1000))),
+            Err(ProducesError::TimedOut)
+        );
+        rt.block_on(call!(addr.patient(100))).unwrap();
+        assert_eq!(
+            rt.block_on(call!(addr.fail())),
+            Err(ProducesError::Canceled)
+        );
+        assert_eq!(
+            rt.block_on(call!(addr.timeouts())).unwrap(),
+            vec!["wait", "wait_ref", "none"]
+        );
+
+        // Without cancellation, slow methods are allowed to finish.
+        let addr = spawn(watchdog.cancel(false));
+        rt.block_on(call!(addr.wait(100))).unwrap();
+        assert!(rt.block_on(call!(addr.timeouts())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn supervisor_test() {
+        use crate::supervisor::{RestartStrategy, Supervisor};
+
+        struct Counter(u32);
+
+        impl Actor for Counter {}
+        impl Counter {
+            async fn inc(&mut self) -> ActorResult<u32> {
+                self.0 += 1;
+                Produces::ok(self.0)
+            }
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+        }
+
+        let rt = Runtime::new();
+        let supervisor = rt.spawn_actor(
+            Supervisor::new(rt.clone(), RestartStrategy::OneForAll)
+                .with_max_restarts(1, Duration::from_secs(10))
+                .with_backoff(Duration::from_millis(10), Duration::from_millis(100)),
+        );
+        let a = rt
+            .block_on(call!(supervisor.supervise(|| Counter(0))))
+            .unwrap();
+        let b = rt
+            .block_on(call!(supervisor.supervise(|| Counter(0))))
+            .unwrap();
+        assert_eq!(rt.block_on(call!(a.inc())), Ok(1));
+        assert_eq!(rt.block_on(call!(b.inc())), Ok(1));
+
+        // Both children are restarted after a backoff, and the existing
+        // addresses still work
+        let start = rt.now();
+        send!(a.fail());
+        assert_eq!(rt.block_on(call!(a.inc())), Ok(1));
+        assert_eq!(rt.block_on(call!(b.inc())), Ok(1));
+        assert!(rt.now() - start >= Duration::from_millis(10));
+
+        // The supervisor stops itself after too many restarts
+        send!(b.fail());
+        assert_eq!(rt.block_on(b.termination()), ExitReason::Error);
+        assert_eq!(rt.block_on(a.termination()), ExitReason::Killed);
+        assert_eq!(rt.block_on(supervisor.termination()), ExitReason::Error);
+
+        // Spawn options still apply after a restart
+        let supervisor = rt.spawn_actor(Supervisor::new(rt.clone(), RestartStrategy::OneForOne));
+        let options = SpawnOptions::new()
+            .name("counter")
+            .capacity(2)
+            .priorities(2);
+        let c = rt
+            .block_on(call!(supervisor.supervise_with(options, || Counter(0))))
+            .unwrap();
+        rt.run_until_idle();
+        send!(c.fail());
+        assert_eq!(rt.block_on(call!(c.with_priority(1).inc())), Ok(1));
+        assert_eq!(c.name(), Some("counter"));
+
+        // Panics whilst restarting a child count as further failures
+        struct Flaky(u32);
+
+        #[async_trait]
+        impl Actor for Flaky {
+            async fn started(&mut self, _addr: Addr<Self>) -> ActorResult<()> {
+                assert_ne!(self.0, 2, "Failed to start");
+                Produces::ok(())
+            }
+        }
+        impl Flaky {
+            async fn generation(&mut self) -> ActorResult<u32> {
+                Produces::ok(self.0)
+            }
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+        }
+
+        let mut generation = 0;
+        let factory = move || {
+            generation += 1;
+            assert_ne!(generation, 3, "Failed to create");
+            Flaky(generation)
+        };
+        let supervisor = rt.spawn_actor(Supervisor::new(rt.clone(), RestartStrategy::OneForOne));
+        let d = rt.block_on(call!(supervisor.supervise(factory))).unwrap();
+        send!(d.fail());
+        assert_eq!(rt.block_on(call!(d.generation())), Ok(4));
+
+        // Children which are killed whilst waiting to be restarted stay stopped
+        let created = Arc::new(Mutex::new(0));
+        let created2 = created.clone();
+        let factory = move || {
+            *created2.lock().unwrap() += 1;
+            Counter(0)
+        };
+        let supervisor = rt.spawn_actor(
+            Supervisor::new(rt.clone(), RestartStrategy::OneForOne)
+                .with_backoff(Duration::from_secs(60), Duration::from_secs(60)),
+        );
+        let e = rt.block_on(call!(supervisor.supervise(factory))).unwrap();
+        let start = rt.now();
+        send!(e.fail());
+        rt.run_until_idle();
+        e.kill();
+        assert_eq!(rt.block_on(e.termination()), ExitReason::Killed);
+        assert!(rt.now() - start < Duration::from_secs(60));
+        rt.advance(Duration::from_secs(120));
+        assert_eq!(*created.lock().unwrap(), 1);
+    }
+
+    // Returns the order in which a set of tasks ran.
+    fn schedule(rt: &Runtime) -> Vec<usize> {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..8 {
+            let order = order.clone();
+            rt.spawn(async move { order.lock().unwrap().push(i) })
+                .unwrap();
+        }
+        rt.run_until_idle();
+        let order = order.lock().unwrap().clone();
+        order
+    }
+
+    #[test]
+    fn seed_test() {
+        let fifo: Vec<_> = (0..8).collect();
+        assert_eq!(schedule(&Runtime::new()), fifo);
+        assert_eq!(
+            schedule(&Runtime::with_seed(7)),
+            schedule(&Runtime::with_seed(7))
+        );
+        assert!((0..8).any(|seed| schedule(&Runtime::with_seed(seed)) != fifo));
+    }
+}
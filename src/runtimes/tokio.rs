@@ -31,6 +31,9 @@ impl timer::SupportsTimers for Runtime {
     fn delay(&self, deadline: Instant) -> Self::Delay {
         tokio::time::sleep_until(deadline.into())
     }
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
 }
 
 #[cfg(test)]
@@ -170,12 +173,1381 @@ mod tests {
         assert!(end_time - start_time < Duration::from_millis(10));
     }
 
+    #[tokio::test]
+    async fn bounded_mailbox_test() {
+        use futures::channel::oneshot;
+
+        struct Blocker;
+
+        impl Actor for Blocker {}
+        impl Blocker {
+            async fn block(&mut self, rx: oneshot::Receiver<()>) {
+                let _ = rx.await;
+            }
+            async fn ping(&mut self) -> ActorResult<u32> {
+                Produces::ok(42)
+            }
+        }
+
+        let addr = Addr::new_with(&Runtime, Blocker, SpawnOptions::new().capacity(1)).unwrap();
+        let (tx, rx) = oneshot::channel();
+
+        // Wait for the actor to start processing the blocking call.
+        call_async!(addr.ping()).await.unwrap().await.unwrap();
+        send!(addr.block(rx));
+        while try_send!(addr.ping()).is_ok() {
+            tokio::task::yield_now().await;
+        }
+
+        // The mailbox is full, so calls are dropped unless they wait for the actor.
+        assert_eq!(try_send!(addr.ping()), Err(SendError::Full));
+        assert_eq!(call!(addr.ping()).await, Err(ProducesError::Canceled));
+        let pending = tokio::spawn(async move {
+            let res = call_async!(addr.ping()).await.unwrap();
+            res.await.unwrap()
+        });
+        tx.send(()).unwrap();
+
+        assert_eq!(pending.await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn stop_kill_test() {
+        use futures::future;
+
+        struct Counter(u32);
+
+        impl Actor for Counter {}
+        impl Counter {
+            async fn inc(&mut self) {
+                self.0 += 1;
+            }
+            async fn get(&mut self) -> ActorResult<u32> {
+                Produces::ok(self.0)
+            }
+            async fn hang(&mut self) {
+                future::pending::<()>().await;
+            }
+        }
+
+        // Queued messages are processed before a graceful stop
+        let addr = spawn_actor(Counter(0));
+        send!(addr.inc());
+        let res = call!(addr.get());
+        addr.stop();
+        assert!(call!(addr.get()).await.is_err());
+        assert_eq!(res.await.unwrap(), 1);
+        assert_eq!(addr.termination().await, ExitReason::Normal);
+
+        // Killing interrupts the current method
+        let addr = spawn_actor(Counter(0));
+        send!(addr.hang());
+        let res = call!(addr.get());
+        addr.kill();
+        assert_eq!(addr.termination().await, ExitReason::Killed);
+        assert!(res.await.is_err());
+        assert_eq!(addr.downgrade().termination().await, ExitReason::Killed);
+    }
+
+    #[tokio::test]
+    async fn lifecycle_test() {
+        use async_trait::async_trait;
+        use futures::channel::mpsc;
+        use futures::StreamExt;
+
+        struct Stubborn {
+            refusals: u32,
+            events: mpsc::UnboundedSender<ExitReason>,
+        }
+
+        #[async_trait]
+        impl Actor for Stubborn {
+            async fn stopping(&mut self, _reason: ExitReason) -> bool {
+                if self.refusals > 0 {
+                    self.refusals -= 1;
+                    false
+                } else {
+                    true
+                }
+            }
+            async fn stopped(&mut self, reason: ExitReason) {
+                tokio::task::yield_now().await;
+                let _ = self.events.unbounded_send(reason);
+            }
+        }
+        impl Stubborn {
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+        }
+
+        let (tx, mut rx) = mpsc::unbounded();
+        let addr = spawn_actor(Stubborn {
+            refusals: 1,
+            events: tx.clone(),
+        });
+        addr.stop();
+        addr.stop();
+        assert_eq!(addr.termination().await, ExitReason::Normal);
+        assert_eq!(rx.next().await, Some(ExitReason::Normal));
+
+        let addr = spawn_actor(Stubborn {
+            refusals: 1,
+            events: tx,
+        });
+        send!(addr.fail());
+        assert_eq!(addr.termination().await, ExitReason::Error);
+        assert_eq!(rx.next().await, Some(ExitReason::Error));
+    }
+
+    #[tokio::test]
+    async fn panic_test() {
+        use async_trait::async_trait;
+
+        #[derive(Default)]
+        struct Fragile {
+            panics: Vec<String>,
+        }
+
+        #[async_trait]
+        impl Actor for Fragile {
+            async fn error(&mut self, error: ActorError) -> bool {
+                let error = error.downcast::<PanicError>().unwrap();
+                self.panics.push(error.message().unwrap().into());
+                false
+            }
+        }
+        impl Fragile {
+            async fn explode(&mut self) -> ActorResult<()> {
+                panic!("Boom")
+            }
+            async fn panics(&mut self) -> ActorResult<Vec<String>> {
+                Produces::ok(self.panics.clone())
+            }
+        }
+
+        let addr = spawn_actor(Fragile::default());
+        send!(addr.explode());
+        let res = call!(addr.explode()).await;
+        assert_eq!(res, Err(ProducesError::Panicked));
+        let res = call!(addr.panics()).await.unwrap();
+        assert_eq!(res, vec!["Boom", "Boom"]);
+    }
+
+    #[tokio::test]
+    async fn call_result_test() {
+        struct Parser;
+
+        impl Actor for Parser {}
+        impl Parser {
+            async fn parse(&mut self, s: &'static str) -> ActorResult<Result<u32, String>> {
+                Produces::ok(s.parse().map_err(|_| format!("Invalid: {}", s)))
+            }
+            async fn explode(&mut self) -> ActorResult<Result<(), String>> {
+                panic!("Boom")
+            }
+        }
+
+        let addr = spawn_actor(Parser);
+        assert_eq!(call_result!(addr.parse("42")).await, Ok(42));
+        assert_eq!(
+            call_result!(addr.parse("x")).await,
+            Err(CallError::Handler("Invalid: x".into()))
+        );
+        // Handler errors don't stop the actor
+        assert_eq!(call_result!(addr.parse("7")).await, Ok(7));
+        assert_eq!(call_result!(addr.explode()).await, Err(CallError::Panicked));
+
+        addr.stop();
+        addr.termination().await;
+        assert_eq!(call_result!(addr.parse("1")).await, Err(CallError::Closed));
+        let detached = Addr::<Parser>::detached();
+        assert_eq!(
+            call_result!(detached.parse("1")).await,
+            Err(CallError::Closed)
+        );
+
+        // A full mailbox is reported separately from a stopped actor
+        let addr = Addr::new_with(&Runtime, Parser, SpawnOptions::new().capacity(1)).unwrap();
+        call_async!(addr.parse("0"))
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        let first = call_result!(addr.parse("1"));
+        assert_eq!(call_result!(addr.parse("2")).await, Err(CallError::Full));
+        assert_eq!(first.await, Ok(1));
+        assert_eq!(call_result!(addr.parse("3")).await, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn recipient_test() {
+        struct Logger(Vec<String>);
+
+        impl Actor for Logger {}
+        impl Logger {
+            async fn log(&mut self, line: String) {
+                self.0.push(line);
+            }
+            async fn count(&mut self, line: &'static str) -> ActorResult<usize> {
+                self.0.push(line.into());
+                Produces::ok(self.0.len())
+            }
+        }
+
+        let addr = spawn_actor(Logger(Vec::new()));
+        let log: Recipient<String> = recipient!(addr.log);
+        let count = recipient!(addr.downgrade().count);
+        log.send("a".into());
+        assert_eq!(count.call("b").await, Ok(2));
+        assert_eq!(count.upgrade().try_call("c").unwrap().await, Ok(3));
+
+        // The weak recipient does not keep the actor alive
+        let weak = log.downgrade();
+        drop(log);
+        drop(addr);
+        assert_eq!(weak.termination().await, ExitReason::Normal);
+        assert_eq!(count.call("d").await, Err(ProducesError::Canceled));
+        assert_eq!(
+            weak.upgrade().try_call("e".into()).err(),
+            Some(SendError::Disconnected)
+        );
+        assert_eq!(
+            format!("{:?}", weak.upgrade()),
+            "Recipient { detached: true }"
+        );
+    }
+
+    #[tokio::test]
+    async fn registry_test() {
+        use crate::registry::Registry;
+
+        trait Named: Actor {}
+
+        struct Service;
+        impl Actor for Service {}
+        impl Named for Service {}
+
+        let registry = spawn_actor(Registry::default());
+        let service = spawn_actor(Service);
+        let registered = call!(registry.register("a", service.downgrade()))
+            .await
+            .unwrap();
+        assert!(registered);
+        let named: WeakAddr<dyn Named> = upcast!(service.downgrade());
+        let registered = call!(registry.register("a", named)).await.unwrap();
+        assert!(registered);
+        let registered = call!(registry.register("a", service.downgrade()))
+            .await
+            .unwrap();
+        assert!(!registered);
+
+        let found: Option<Addr<Service>> = call!(registry.lookup("a")).await.unwrap();
+        assert_eq!(found, Some(service.clone()));
+        let found: Option<Addr<dyn Named>> = call!(registry.lookup("a")).await.unwrap();
+        assert_eq!(found.unwrap(), service);
+        let found: Option<Addr<Service>> = call!(registry.lookup("b")).await.unwrap();
+        assert_eq!(found, None);
+
+        // Entries are removed when the actor terminates
+        service.stop();
+        service.termination().await;
+        let found: Option<Addr<Service>> = call!(registry.lookup("a")).await.unwrap();
+        assert_eq!(found, None);
+
+        let a = call!(registry.get_or_spawn("a", Runtime, || Service))
+            .await
+            .unwrap();
+        let b = call!(registry.get_or_spawn("a", Runtime, || Service))
+            .await
+            .unwrap();
+        assert_eq!(a, b);
+        let options = SpawnOptions::new().name("service");
+        let c = call!(registry.get_or_spawn_with("c", Runtime, options, || Service))
+            .await
+            .unwrap();
+        assert_eq!(c.name(), Some("service"));
+        let removed = call!(registry.unregister("c", c.downgrade())).await;
+        assert_eq!(removed, Ok(true));
+        let removed = call!(registry.unregister("a", a.downgrade())).await;
+        assert_eq!(removed, Ok(true));
+        let found: Option<Addr<Service>> = call!(registry.lookup("a")).await.unwrap();
+        assert_eq!(found, None);
+
+        // Unregistering stops waiting for the actor to terminate
+        for _ in 0..3 {
+            call!(registry.register("a", a.downgrade())).await.unwrap();
+            call!(registry.unregister("a", a.downgrade()))
+                .await
+                .unwrap();
+        }
+        let found: Option<Addr<Service>> = call!(registry.lookup("a")).await.unwrap();
+        assert_eq!(found, None);
+        assert_eq!(registry.stats().futures(), 0);
+    }
+
+    #[tokio::test]
+    async fn pool_test() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use futures::channel::oneshot;
+
+        use crate::pool::{Pool, Routing};
+
+        struct Worker(usize);
+
+        impl Actor for Worker {}
+        impl Worker {
+            async fn id(&mut self) -> ActorResult<usize> {
+                Produces::ok(self.0)
+            }
+            async fn block(&mut self, rx: oneshot::Receiver<()>) {
+                let _ = rx.await;
+            }
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+        }
+
+        let next = Arc::new(AtomicUsize::new(0));
+        let factory = move || Worker(next.fetch_add(1, Ordering::Relaxed));
+        let pool = Pool::new(Runtime, 3, Routing::RoundRobin, factory).unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..6 {
+            ids.push(call!(pool.id()).await.unwrap());
+        }
+        assert_eq!(ids, vec![0, 1, 2, 0, 1, 2]);
+
+        // Keys are only moved to new workers when growing the pool
+        let before: Vec<_> = (0..100usize).map(|key| pool.route(&key)).collect();
+        pool.resize(4).unwrap();
+        assert_eq!(pool.len(), 4);
+        for (key, worker) in before.iter().enumerate() {
+            let worker2 = pool.route(&key);
+            let id = call!(worker2.id()).await.unwrap();
+            assert!(id == 3 || worker2 == *worker);
+        }
+
+        // Random routing eventually chooses every worker
+        let next = Arc::new(AtomicUsize::new(0));
+        let factory = move || Worker(next.fetch_add(1, Ordering::Relaxed));
+        let random = Pool::new(Runtime, 8, Routing::Random, factory).unwrap();
+        let mut chosen = std::collections::HashSet::new();
+        while chosen.len() < 8 {
+            chosen.insert(call!(random.id()).await.unwrap());
+        }
+        assert!(chosen.iter().all(|&id| id < 8));
+
+        // Removed workers are stopped
+        let removed = pool.workers().pop().unwrap();
+        pool.resize(2).unwrap();
+        assert_eq!(removed.termination().await, ExitReason::Normal);
+
+        // Workers which terminate are replaced
+        for routing in [Routing::RoundRobin, Routing::Random, Routing::LeastLoaded] {
+            let pool = Pool::new(Runtime, 2, routing, || Worker(0)).unwrap();
+            let failed = pool.workers()[0].clone();
+            send!(failed.fail());
+            assert_eq!(failed.termination().await, ExitReason::Error);
+            for _ in 0..4 {
+                assert_eq!(call!(pool.id()).await, Ok(0));
+            }
+            // Workers are replaced once they are chosen, which may take a few
+            // attempts when they are chosen at random.
+            while pool.workers().contains(&failed) {
+                assert_eq!(call!(pool.id()).await, Ok(0));
+            }
+            assert_eq!(pool.len(), 2);
+        }
+
+        let next = Arc::new(AtomicUsize::new(0));
+        let factory = move || Worker(next.fetch_add(1, Ordering::Relaxed));
+        let pool = Pool::new(Runtime, 2, Routing::LeastLoaded, factory).unwrap();
+        let busy = pool.workers()[0].clone();
+        let (tx, rx) = oneshot::channel::<()>();
+        send!(busy.block(rx));
+        send!(busy.id());
+        for _ in 0..4 {
+            assert_eq!(call!(pool.id()).await, Ok(1));
+        }
+        drop(tx);
+
+        let termination = pool.termination();
+        drop((pool, busy));
+        assert_eq!(termination.await, ExitReason::Normal);
+
+        // The pool reports any worker which stopped abnormally
+        let pool = Pool::new(Runtime, 2, Routing::RoundRobin, || Worker(0)).unwrap();
+        let termination = pool.termination();
+        let workers = pool.workers();
+        drop(pool);
+        workers[1].kill();
+        drop(workers);
+        assert_eq!(termination.await, ExitReason::Killed);
+    }
+
+    #[tokio::test]
+    async fn group_test() {
+        use std::time::Duration;
+
+        use async_trait::async_trait;
+        use futures::channel::{mpsc, oneshot};
+        use futures::future::{FutureExt, Shared};
+        use futures::StreamExt;
+
+        use crate::group::Group;
+
+        type Gate = Shared<oneshot::Receiver<()>>;
+
+        #[async_trait]
+        trait Listener: Actor {
+            async fn notify(&mut self, event: String);
+            async fn query(&mut self, gate: Option<Gate>) -> ActorResult<usize>;
+        }
+
+        struct Member {
+            id: usize,
+            events: mpsc::UnboundedSender<(usize, String)>,
+        }
+
+        impl Actor for Member {}
+
+        #[async_trait]
+        impl Listener for Member {
+            async fn notify(&mut self, event: String) {
+                let _ = self.events.unbounded_send((self.id, event));
+            }
+            // Every member except the first waits for the gate to open.
+            async fn query(&mut self, gate: Option<Gate>) -> ActorResult<usize> {
+                if let Some(gate) = gate.filter(|_| self.id != 0) {
+                    let _ = gate.await;
+                }
+                Produces::ok(self.id)
+            }
+        }
+
+        let (tx, mut rx) = mpsc::unbounded();
+        let members: Vec<_> = (0..3)
+            .map(|id| {
+                spawn_actor(Member {
+                    id,
+                    events: tx.clone(),
+                })
+            })
+            .collect();
+        let group: Group<dyn Listener> = Group::new();
+        for member in &members {
+            assert!(group.join(upcast!(member.downgrade())));
+        }
+        assert!(!group.join(upcast!(members[0].downgrade())));
+        assert_eq!(group.len(), 3);
+
+        send_all!(group.notify("hello".to_string()));
+        let mut events: Vec<_> = (&mut rx).take(3).collect().await;
+        events.sort();
+        assert_eq!(events[0], (0, "hello".to_string()));
+        assert_eq!(events[2], (2, "hello".to_string()));
+
+        // The group expression is only evaluated once, even though it is also
+        // used for trace output
+        let mut evaluated = 0;
+        send_all!({
+            evaluated += 1;
+            group.clone()
+        }
+        .notify("again".to_string()));
+        assert_eq!(evaluated, 1);
+        let events: Vec<_> = (&mut rx).take(3).collect().await;
+        assert!(events.iter().all(|(_, event)| event == "again"));
+
+        let mut ids = call_all!(group.query(None)).await;
+        ids.sort_unstable();
+        assert_eq!(ids, vec![0, 1, 2]);
+        let (open, gate) = oneshot::channel();
+        let gate = Some(gate.shared());
+        let ids = call_all!(group.query(gate.clone())).quorum(1).await;
+        assert_eq!(ids, vec![0]);
+        let ids = call_all!(group.query(gate))
+            .timeout(&Runtime, Duration::from_millis(10))
+            .await;
+        assert_eq!(ids, vec![0]);
+        open.send(()).unwrap();
+
+        // Terminated members are removed
+        members[1].stop();
+        members[1].termination().await;
+        assert_eq!(group.len(), 2);
+        assert!(group.leave(&upcast!(members[0].downgrade())));
+        assert_eq!(group.members(), vec![members[2].clone()]);
+    }
+
+    #[tokio::test]
+    async fn stream_test() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use async_trait::async_trait;
+        use futures::channel::{mpsc, oneshot};
+        use futures::stream::{self, StreamExt};
+
+        struct Collector {
+            items: Vec<u64>,
+            gate: Option<oneshot::Receiver<()>>,
+            done: Option<oneshot::Sender<Vec<u64>>>,
+        }
+
+        impl Actor for Collector {}
+
+        #[async_trait]
+        impl StreamHandler<u64> for Collector {
+            // The first item waits for the gate to open.
+            async fn handle(&mut self, item: u64) -> ActorResult<()> {
+                if let Some(gate) = self.gate.take() {
+                    let _ = gate.await;
+                }
+                self.items.push(item);
+                Produces::ok(())
+            }
+            async fn finished(&mut self) -> ActorResult<()> {
+                let _ = self.done.take().unwrap().send(self.items.clone());
+                Produces::ok(())
+            }
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let (open, gate) = oneshot::channel();
+        let addr = spawn_actor(Collector {
+            items: Vec::new(),
+            gate: Some(gate),
+            done: Some(tx),
+        });
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let pulled2 = pulled.clone();
+        addr.add_stream(stream::iter(0..5).inspect(move |_| {
+            pulled2.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        // The next item is not requested until the handler has returned
+        while pulled.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(pulled.load(Ordering::SeqCst), 1);
+        open.send(()).unwrap();
+        assert_eq!(rx.await.unwrap(), vec![0, 1, 2, 3, 4]);
+
+        // The stream is dropped when the actor stops
+        let (tx, rx) = mpsc::unbounded();
+        addr.add_stream(rx);
+        tx.unbounded_send(1).unwrap();
+        addr.stop();
+        addr.termination().await;
+        assert!(tx.is_closed());
+    }
+
+    #[tokio::test]
+    async fn call_stream_test() {
+        use futures::channel::oneshot;
+        use futures::stream::{self, StreamExt};
+
+        struct Pager(Vec<u32>);
+
+        impl Actor for Pager {}
+        impl Pager {
+            async fn pages(&mut self, size: usize) -> ActorResult<Streaming<Vec<u32>>> {
+                let pages: Vec<_> = self.0.chunks(size).map(|page| page.to_vec()).collect();
+                Produces::ok(Streaming::new(stream::iter(pages)).with_buffer(1))
+            }
+            async fn forever(&mut self, guard: oneshot::Sender<()>) -> ActorResult<Streaming<u32>> {
+                Streaming::ok(
+                    stream::once(async { 1 })
+                        .chain(stream::pending())
+                        .map(move |x| {
+                            let _ = &guard;
+                            x
+                        }),
+                )
+            }
+            async fn fail(&mut self) -> ActorResult<Streaming<u32>> {
+                Err("Failed".into())
+            }
+        }
+
+        let addr = spawn_actor(Pager((0..5).collect()));
+        let pages: Vec<_> = call_stream!(addr.pages(2)).collect().await;
+        assert_eq!(pages, vec![vec![0, 1], vec![2, 3], vec![4]]);
+
+        // Dropping the stream cancels it on the actor side
+        let (tx, rx) = oneshot::channel();
+        let mut values = call_stream!(addr.forever(tx));
+        assert_eq!(values.next().await, Some(1));
+        drop(values);
+        assert!(rx.await.is_err());
+
+        // Errors end the stream, and stop the actor as usual
+        let values: Vec<_> = call_stream!(addr.fail()).collect().await;
+        assert!(values.is_empty());
+        assert_eq!(addr.termination().await, ExitReason::Error);
+    }
+
+    #[tokio::test]
+    async fn produces_test() {
+        use std::time::Duration;
+
+        use futures::channel::oneshot;
+
+        struct Slow;
+
+        impl Actor for Slow {}
+        impl Slow {
+            async fn value(&mut self, x: u32) -> ActorResult<u32> {
+                Produces::ok(x)
+            }
+            async fn delayed(&mut self, x: u32, gate: oneshot::Receiver<()>) -> ActorResult<u32> {
+                let _ = gate.await;
+                Produces::ok(x)
+            }
+            async fn fail(&mut self) -> ActorResult<u32> {
+                Err("Failed".into())
+            }
+        }
+
+        // Values which are already available are transformed immediately
+        let res = Produces::Value(2).map(|x| x * 2);
+        assert!(matches!(res, Produces::Value(4)));
+        let res = Produces::<u32>::None.or_else(|_| Produces::Value(1));
+        assert!(matches!(res, Produces::Value(1)));
+        let res = Produces::join_all(vec![Produces::Value(1), Produces::Value(2)]);
+        assert!(matches!(res, Produces::Value(ref v) if *v == [1, 2]));
+
+        let addr = spawn_actor(Slow);
+        let addr2 = addr.clone();
+        let res = call!(addr.value(2))
+            .map(|x| x + 1)
+            .and_then(move |x| call!(addr2.value(x * 10)));
+        assert_eq!(res.await, Ok(30));
+
+        let (open, gate) = oneshot::channel();
+        let res = call!(addr.delayed(1, gate)).timeout(&Runtime, Duration::from_millis(10));
+        assert_eq!(res.await, Err(ProducesError::TimedOut));
+        open.send(()).unwrap();
+
+        let res = Produces::join_all(vec![
+            call!(addr.value(1)),
+            Produces::Value(2),
+            call!(addr.value(3)),
+        ]);
+        assert_eq!(res.await, Ok(vec![1, 2, 3]));
+
+        let other = spawn_actor(Slow);
+        let (_open, gate) = oneshot::channel();
+        let res = Produces::select(vec![
+            call!(other.delayed(1, gate)),
+            Produces::None,
+            call!(addr.value(2)),
+        ]);
+        assert_eq!(res.await, Ok(2));
+
+        let res = call!(addr.fail()).or_else(|e| {
+            assert_eq!(e, ProducesError::Canceled);
+            Produces::Value(5)
+        });
+        assert_eq!(res.await, Ok(5));
+    }
+
+    #[tokio::test]
+    async fn deadline_test() {
+        use std::time::Duration;
+
+        use futures::channel::oneshot;
+
+        struct Counter(u32);
+
+        impl Actor for Counter {}
+        impl Counter {
+            async fn block(&mut self, gate: oneshot::Receiver<()>) {
+                let _ = gate.await;
+            }
+            async fn inc(&mut self) -> ActorResult<u32> {
+                self.0 += 1;
+                Produces::ok(self.0)
+            }
+        }
+
+        let addr = spawn_actor(Counter(0));
+        let (open, gate) = oneshot::channel();
+        send!(addr.block(gate));
+        let expired = addr.with_timeout(&Runtime, Duration::ZERO);
+        send!(expired.inc());
+        let res = call!(expired.inc());
+        let res2 = call!(addr.with_timeout(&Runtime, Duration::from_secs(10)).inc());
+        open.send(()).unwrap();
+        assert_eq!(res.await, Err(ProducesError::TimedOut));
+        assert_eq!(res2.await, Ok(1));
+
+        // Calls whose result is no longer wanted are skipped
+        let (open, gate) = oneshot::channel();
+        send!(addr.block(gate));
+        drop(call!(addr.inc()));
+        open.send(()).unwrap();
+        assert_eq!(call!(addr.inc()).await, Ok(2));
+
+        // Skipped messages are not counted as processed, leaving `started`,
+        // both blocks and both increments
+        assert_eq!(addr.stats().processed(), 5);
+    }
+
+    #[tokio::test]
+    async fn priority_test() {
+        use futures::channel::oneshot;
+
+        struct Recorder(Vec<u32>);
+
+        impl Actor for Recorder {}
+        impl Recorder {
+            async fn block(&mut self, gate: oneshot::Receiver<()>) {
+                let _ = gate.await;
+            }
+            async fn record(&mut self, value: u32) {
+                self.0.push(value);
+            }
+            async fn get(&mut self) -> ActorResult<Vec<u32>> {
+                Produces::ok(self.0.clone())
+            }
+        }
+
+        let addr = Addr::new_with(
+            &Runtime,
+            Recorder(Vec::new()),
+            SpawnOptions::new().priorities(2),
+        )
+        .unwrap();
+        let (open, gate) = oneshot::channel();
+        send!(addr.block(gate));
+        send!(addr.record(1));
+        send!(addr.record(2));
+        send!(addr.with_priority(1).record(3));
+        send!(addr.with_priority(1).record(4));
+
+        // Priorities beyond the highest level are clamped
+        send!(addr.with_priority(5).record(5));
+        open.send(()).unwrap();
+        assert_eq!(call!(addr.get()).await.unwrap(), vec![3, 4, 5, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn call_ref_test() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use futures::channel::oneshot;
+        use futures::future::{FutureExt, Shared};
+
+        type Gate = Shared<oneshot::Receiver<()>>;
+
+        struct Cache {
+            value: u32,
+            readers: Arc<AtomicUsize>,
+        }
+
+        impl Actor for Cache {}
+        impl Cache {
+            async fn get(&self, gate: Gate) -> ActorResult<u32> {
+                self.readers.fetch_add(1, Ordering::SeqCst);
+                let _ = gate.await;
+                self.readers.fetch_sub(1, Ordering::SeqCst);
+                Produces::ok(self.value)
+            }
+            async fn set(&mut self, value: u32) -> ActorResult<usize> {
+                self.value = value;
+                Produces::ok(self.readers.load(Ordering::SeqCst))
+            }
+        }
+
+        let readers = Arc::new(AtomicUsize::new(0));
+        let addr = spawn_actor(Cache {
+            value: 0,
+            readers: readers.clone(),
+        });
+        let (open, gate) = oneshot::channel();
+        let gate = gate.shared();
+        let reads: Vec<_> = (0..3).map(|_| call_ref!(addr.get(gate.clone()))).collect();
+        let write = call!(addr.set(1));
+        let read = call_ref!(addr.get(gate));
+
+        // Readers run concurrently, and the writer waits for them to finish
+        for _ in 0..100 {
+            if readers.load(Ordering::SeqCst) == 3 {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(readers.load(Ordering::SeqCst), 3);
+        open.send(()).unwrap();
+        for res in reads {
+            assert_eq!(res.await.unwrap(), 0);
+        }
+        assert_eq!(write.await.unwrap(), 0);
+        assert_eq!(read.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn context_test() {
+        use async_trait::async_trait;
+
+        struct Counter(u32);
+
+        impl Actor for Counter {}
+
+        #[async_trait]
+        trait Incrementer: Actor {
+            async fn inc(&mut self) -> ActorResult<u32>;
+
+            // Methods of trait-object actors receive the context of the trait object.
+            async fn inc_twice(&mut self, ctx: &ActorContext<dyn Incrementer>) -> ActorResult<()> {
+                let addr = ctx.addr();
+                send!(addr.inc());
+                send!(addr.inc());
+                Produces::ok(())
+            }
+        }
+
+        #[async_trait]
+        impl Incrementer for Counter {
+            async fn inc(&mut self) -> ActorResult<u32> {
+                self.0 += 1;
+                Produces::ok(self.0)
+            }
+        }
+
+        impl Counter {
+            async fn stop_self(&mut self, ctx: &ActorContext<Self>) {
+                ctx.stop();
+            }
+            async fn add_later(&mut self, ctx: &ActorContext<Self>, n: u32) -> ActorResult<()> {
+                // The context can be moved into futures spawned off the actor's task.
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    for _ in 0..n {
+                        send!(ctx.addr().inc());
+                    }
+                });
+                Produces::ok(())
+            }
+        }
+
+        let addr = spawn_actor(Counter(0));
+        let trait_addr: Addr<dyn Incrementer> = upcast!(addr.clone());
+        call!(trait_addr.inc_twice(_)).await.unwrap();
+        assert_eq!(call!(trait_addr.inc()).await.unwrap(), 3);
+
+        call!(addr.add_later(_, 2)).await.unwrap();
+        let mut value = 0;
+        while value < 6 {
+            value = call!(addr.inc()).await.unwrap();
+        }
+
+        send!(addr.stop_self(_));
+        assert_eq!(addr.termination().await, ExitReason::Normal);
+    }
+
+    #[tokio::test]
+    async fn monitor_link_test() {
+        use async_trait::async_trait;
+        use futures::channel::mpsc;
+        use futures::StreamExt;
+
+        struct Worker;
+
+        impl Actor for Worker {}
+        impl Worker {
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+            async fn link<A: AddrLike>(&mut self, ctx: &ActorContext<Self>, addr: A) {
+                ctx.link(&addr);
+            }
+        }
+
+        struct Watcher(mpsc::UnboundedSender<(MonitorId, ExitReason)>);
+
+        impl Actor for Watcher {}
+
+        #[async_trait]
+        impl Monitor for Watcher {
+            async fn terminated(&mut self, id: MonitorId, reason: ExitReason) -> ActorResult<()> {
+                self.0.unbounded_send((id, reason)).unwrap();
+                Produces::ok(())
+            }
+        }
+
+        impl Watcher {
+            async fn watch(
+                &mut self,
+                ctx: &ActorContext<Self>,
+                addr: Addr<Worker>,
+            ) -> ActorResult<MonitorId> {
+                Produces::ok(ctx.monitor(&addr))
+            }
+            async fn unwatch(
+                &mut self,
+                ctx: &ActorContext<Self>,
+                id: MonitorId,
+            ) -> ActorResult<bool> {
+                Produces::ok(ctx.demonitor(id))
+            }
+        }
+
+        let (tx, mut rx) = mpsc::unbounded();
+        let watcher = spawn_actor(Watcher(tx));
+        let a = spawn_actor(Worker);
+        let b = spawn_actor(Worker);
+        let id_a = call!(watcher.watch(_, a.clone())).await.unwrap();
+        let id_b = call!(watcher.watch(_, b.clone())).await.unwrap();
+        assert_ne!(id_a, id_b);
+
+        a.stop();
+        assert_eq!(rx.next().await, Some((id_a, ExitReason::Normal)));
+        b.kill();
+        assert_eq!(rx.next().await, Some((id_b, ExitReason::Killed)));
+
+        // Removed monitors do not fire
+        let a = spawn_actor(Worker);
+        let b = spawn_actor(Worker);
+        let id_a = call!(watcher.watch(_, a.clone())).await.unwrap();
+        let id_b = call!(watcher.watch(_, b.clone())).await.unwrap();
+        assert_eq!(call!(watcher.unwatch(_, id_a)).await, Ok(true));
+        assert_eq!(call!(watcher.unwatch(_, id_a)).await, Ok(false));
+        a.kill();
+        assert_eq!(a.termination().await, ExitReason::Killed);
+        b.stop();
+        assert_eq!(rx.next().await, Some((id_b, ExitReason::Normal)));
+        assert_eq!(call!(watcher.unwatch(_, id_b)).await, Ok(false));
+
+        // Abnormal termination propagates across links
+        let a = spawn_actor(Worker);
+        let b = spawn_actor(Worker);
+        call!(a.link(_, b.clone())).await.unwrap();
+        send!(b.fail());
+        assert_eq!(b.termination().await, ExitReason::Error);
+        assert_eq!(a.termination().await, ExitReason::Killed);
+
+        // Links can be made using any kind of address
+        let a = spawn_actor(Worker);
+        let b = spawn_actor(Worker);
+        call!(a.link(_, b.downgrade())).await.unwrap();
+        send!(a.fail());
+        assert_eq!(b.termination().await, ExitReason::Killed);
+
+        let a = spawn_actor(Worker);
+        let pool = crate::pool::Pool::new(Runtime, 2, crate::pool::Routing::RoundRobin, || Worker)
+            .unwrap();
+        call!(a.link(_, pool.clone())).await.unwrap();
+        send!(a.fail());
+        for worker in pool.workers() {
+            assert_eq!(worker.termination().await, ExitReason::Killed);
+        }
+
+        // But a graceful stop does not
+        let a = spawn_actor(Worker);
+        let b = spawn_actor(Worker);
+        call!(a.link(_, b.clone())).await.unwrap();
+        b.stop();
+        assert_eq!(b.termination().await, ExitReason::Normal);
+        a.stop();
+        assert_eq!(a.termination().await, ExitReason::Normal);
+    }
+
+    #[tokio::test]
+    async fn actor_id_test() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        struct Named;
+
+        impl Actor for Named {}
+
+        fn hash<T: Hash>(value: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let a = Addr::new_with(&Runtime, Named, SpawnOptions::new().name("alice")).unwrap();
+        let b = spawn_actor(Named);
+        assert_ne!(a.id(), b.id());
+        assert_eq!(a.name(), Some("alice"));
+        assert_eq!(b.name(), None);
+        assert_eq!(Addr::<Named>::detached().id(), None);
+        assert!(format!("{:?}", a).contains("alice"));
+
+        // Weak addresses keep their identity after the actor has terminated
+        let weak = a.downgrade();
+        let before = hash(&weak);
+        a.kill();
+        assert_eq!(weak.termination().await, ExitReason::Killed);
+        drop(a);
+        assert_eq!(hash(&weak), before);
+        assert_eq!(weak.name(), Some("alice"));
+        assert_ne!(weak, WeakAddr::<Named>::detached());
+        assert_eq!(weak.termination().await, ExitReason::Killed);
+        // The rest of the actor's state is not kept alive by weak addresses
+        assert_eq!(weak.stats().processed(), 0);
+        assert_eq!(b.downgrade().stats().processed(), 1);
+    }
+
+    #[tokio::test]
+    async fn stats_test() {
+        use std::time::Instant;
+
+        use async_trait::async_trait;
+        use futures::channel::oneshot;
+        use futures::future;
+
+        struct Worker;
+
+        #[async_trait]
+        impl Actor for Worker {
+            async fn error(&mut self, _error: ActorError) -> bool {
+                false
+            }
+        }
+        impl Worker {
+            async fn block(&mut self, rx: oneshot::Receiver<()>) {
+                let _ = rx.await;
+            }
+            async fn ping(&mut self) {}
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+        }
+
+        let addr = spawn_actor(Worker);
+        let stats = addr.stats();
+        let (tx, rx) = oneshot::channel();
+        let blocked = call!(addr.block(rx));
+        while !stats.is_busy() {
+            tokio::task::yield_now().await;
+        }
+        // The actor is busy for at least as long as it is observed to be busy
+        let observed = Instant::now();
+        tokio::task::yield_now().await;
+        let elapsed = observed.elapsed();
+        tx.send(()).unwrap();
+        blocked.await.unwrap();
+        call!(addr.ping()).await.unwrap();
+        assert!(!stats.is_busy());
+        assert!(stats.busy_time() >= elapsed);
+
+        let (tx, rx) = oneshot::channel();
+        call!(addr.fail()).await.unwrap_err();
+        send!(addr.block(rx));
+        send!(addr.ping());
+        addr.send_fut(future::pending());
+        while stats.futures() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(stats.is_busy());
+        assert_eq!(stats.queue_len(), 1);
+        assert_eq!(stats.errors(), 1);
+
+        tx.send(()).unwrap();
+        call!(addr.ping()).await.unwrap();
+        // `started`, three calls to `ping`, `fail` and two calls to `block`
+        assert_eq!(stats.processed(), 7);
+        assert_eq!(stats.queue_len(), 0);
+        assert_eq!(stats.futures(), 1);
+
+        // Messages which will never be processed are not counted as queued
+        let (_tx, rx) = oneshot::channel();
+        send!(addr.block(rx));
+        send!(addr.fail());
+        while stats.queue_len() != 1 || !stats.is_busy() {
+            tokio::task::yield_now().await;
+        }
+        addr.kill();
+        addr.termination().await;
+        assert_eq!(stats.queue_len(), 0);
+        send!(addr.fail());
+        addr.stop();
+        assert_eq!(stats.queue_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn metrics_test() {
+        use std::sync::Arc;
+
+        use async_trait::async_trait;
+        use futures::channel::oneshot;
+
+        use crate::metrics::{self, PrometheusRecorder};
+
+        struct Worker;
+
+        #[async_trait]
+        impl Actor for Worker {
+            async fn error(&mut self, _error: ActorError) -> bool {
+                false
+            }
+        }
+        impl Worker {
+            async fn block(&mut self, rx: oneshot::Receiver<()>) {
+                let _ = rx.await;
+            }
+            async fn ping(&mut self) {}
+            async fn fail(&mut self) -> ActorResult<()> {
+                Err("Failed".into())
+            }
+        }
+
+        // Removes the recorder once the test completes, even if it fails.
+        struct RemoveRecorder;
+
+        impl Drop for RemoveRecorder {
+            fn drop(&mut self) {
+                metrics::remove_recorder();
+            }
+        }
+
+        let recorder = Arc::new(PrometheusRecorder::new());
+        assert!(metrics::set_recorder(recorder.clone()).is_none());
+        let _guard = RemoveRecorder;
+
+        // Finds the value of the metric with the given name and labels.
+        let value = |name: &str, method: &str| -> Option<String> {
+            let prefix = format!(
+                "{}{{actor=\"{}\",method=\"{}\"",
+                name,
+                std::any::type_name::<Worker>(),
+                method
+            );
+            let rendered = recorder.render();
+            let line = rendered.lines().find(|line| line.starts_with(&prefix))?;
+            Some(line.rsplit(' ').next().unwrap().to_string())
+        };
+
+        let addr = spawn_actor(Worker);
+        call!(addr.ping()).await.unwrap();
+        call!(addr.fail()).await.unwrap_err();
+
+        let (tx, rx) = oneshot::channel();
+        send!(addr.block(rx));
+        send!(addr.ping());
+        let queue = format!(
+            "act_zero_queue_depth{{actor=\"{}\",id=\"{}\"}} 1",
+            std::any::type_name::<Worker>(),
+            addr.id().unwrap()
+        );
+        while !recorder.render().contains(&queue) {
+            tokio::task::yield_now().await;
+        }
+        // Calls which are skipped because the caller gave up are counted as dropped.
+        drop(call!(addr.fail()));
+        tx.send(()).unwrap();
+        call!(addr.ping()).await.unwrap();
+
+        assert_eq!(value("act_zero_messages_sent_total", "ping").unwrap(), "3");
+        assert_eq!(
+            value("act_zero_messages_handled_total", "ping").unwrap(),
+            "3"
+        );
+        assert_eq!(
+            value("act_zero_messages_errored_total", "ping").unwrap(),
+            "0"
+        );
+        assert_eq!(
+            value("act_zero_messages_errored_total", "fail").unwrap(),
+            "1"
+        );
+        assert_eq!(
+            value("act_zero_messages_handled_total", "fail").unwrap(),
+            "1"
+        );
+        assert_eq!(
+            value("act_zero_messages_dropped_total", "fail").unwrap(),
+            "1"
+        );
+        assert_eq!(
+            value("act_zero_handler_duration_seconds_count", "block").unwrap(),
+            "1"
+        );
+        assert_eq!(
+            value("act_zero_mailbox_wait_seconds_count", "ping").unwrap(),
+            "3"
+        );
+
+        // Messages which cannot be delivered are counted as dropped.
+        addr.kill();
+        addr.termination().await;
+        send!(addr.ping());
+        assert_eq!(value("act_zero_messages_sent_total", "ping").unwrap(), "4");
+        assert_eq!(
+            value("act_zero_messages_dropped_total", "ping").unwrap(),
+            "1"
+        );
+        assert!(!recorder.render().contains(&queue));
+    }
+
+    #[tokio::test]
+    async fn deadlock_test() {
+        struct First;
+        struct Second;
+
+        impl Actor for First {}
+        impl Actor for Second {}
+
+        impl First {
+            // Returns `true` if either actor detected the deadlock.
+            async fn start(
+                &mut self,
+                ctx: &ActorContext<Self>,
+                second: Addr<Second>,
+            ) -> ActorResult<bool> {
+                let first = ctx.addr();
+                Produces::ok(match call!(second.relay(first)).await {
+                    Ok(detected) => detected,
+                    Err(e) => e == ProducesError::Deadlock,
+                })
+            }
+            async fn ping(&mut self) {}
+        }
+        impl Second {
+            async fn relay(&mut self, first: Addr<First>) -> ActorResult<bool> {
+                Produces::ok(call!(first.ping()).await == Err(ProducesError::Deadlock))
+            }
+        }
+
+        let options = SpawnOptions::new().detect_deadlocks(true);
+        let first = Addr::new_with(&Runtime, First, options.clone()).unwrap();
+        let second = Addr::new_with(&Runtime, Second, options).unwrap();
+        assert!(call!(first.start(_, second.clone())).await.unwrap());
+
+        // Both actors are able to continue afterwards.
+        call!(first.ping()).await.unwrap();
+        assert!(!call!(second.relay(first)).await.unwrap());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn tracing_test() {
+        use std::collections::HashMap;
+        use std::fmt::Debug;
+        use std::sync::{Arc, Mutex};
+
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Instrument, Metadata, Subscriber};
+        use tracing_core::span::Current;
+
+        struct SpanData {
+            metadata: &'static Metadata<'static>,
+            parent: Option<u64>,
+            fields: HashMap<&'static str, String>,
+        }
+
+        impl Visit for SpanData {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.fields.insert(field.name(), format!("{:?}", value));
+            }
+        }
+
+        // Records every span which is created, identified by its index plus one,
+        // and which spans are currently entered.
+        #[derive(Clone, Default)]
+        struct Recorder {
+            spans: Arc<Mutex<Vec<SpanData>>>,
+            stack: Arc<Mutex<Vec<Id>>>,
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+                let parent = if attrs.is_contextual() {
+                    self.stack.lock().unwrap().last().cloned()
+                } else {
+                    attrs.parent().cloned()
+                };
+                let mut data = SpanData {
+                    metadata: attrs.metadata(),
+                    parent: parent.map(|id| id.into_u64()),
+                    fields: HashMap::new(),
+                };
+                attrs.record(&mut data);
+                let mut spans = self.spans.lock().unwrap();
+                spans.push(data);
+                Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, span: &Id, values: &Record<'_>) {
+                values.record(&mut self.spans.lock().unwrap()[span.into_u64() as usize - 1]);
+            }
+            fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+            fn event(&self, _event: &Event<'_>) {}
+            fn enter(&self, span: &Id) {
+                self.stack.lock().unwrap().push(span.clone());
+            }
+            fn exit(&self, _span: &Id) {
+                self.stack.lock().unwrap().pop();
+            }
+            fn current_span(&self) -> Current {
+                match self.stack.lock().unwrap().last() {
+                    Some(id) => {
+                        let metadata =
+                            self.spans.lock().unwrap()[id.into_u64() as usize - 1].metadata;
+                        Current::new(id.clone(), metadata)
+                    }
+                    None => Current::none(),
+                }
+            }
+        }
+
+        struct Echo;
+
+        impl Actor for Echo {}
+        impl Echo {
+            async fn echo(&mut self, x: u32) -> ActorResult<u32> {
+                Produces::ok(x)
+            }
+        }
+
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+        let addr = Addr::new_with(&Runtime, Echo, SpawnOptions::new().name("echo")).unwrap();
+        let caller = tracing::info_span!("caller");
+        let res = async { call!(addr.echo(42)).await }
+            .instrument(caller)
+            .await;
+        assert_eq!(res.unwrap(), 42);
+
+        // The handler runs in a child of the caller's span
+        let spans = recorder.spans.lock().unwrap();
+        let caller = spans
+            .iter()
+            .position(|span| span.metadata.name() == "caller")
+            .unwrap() as u64
+            + 1;
+        let message = spans
+            .iter()
+            .find(|span| span.fields.get("method").map(String::as_str) == Some("\"echo\""))
+            .unwrap();
+        assert_eq!(message.parent, Some(caller));
+        assert_eq!(message.fields["actor.id"], addr.id().unwrap().to_string());
+        assert_eq!(message.fields["actor.name"], "\"echo\"");
+        assert!(message.fields.contains_key("queue_wait_us"));
+        assert!(message.fields.contains_key("exec_us"));
+    }
+
     // Tests that .termination() waits for the Actor to be dropped.
     // Note that this probably won't race anyway, tokio would need
     // rt-threaded feature.
     #[tokio::test]
     async fn wait_drop_test() {
-        use std::time::{Duration, Instant};
+        use std::time::Duration;
         struct WaitDrop {
             tx: std::sync::mpsc::SyncSender<u32>,
         }
@@ -0,0 +1,89 @@
+//! Integration with the `tracing` crate. When the `tracing` feature is enabled,
+//! each message is run inside a span which is a child of the span that was
+//! current when the message was sent.
+
+use crate::addr::{RefRunFn, RunFn};
+
+#[cfg(feature = "tracing")]
+mod imp {
+    use std::time::Instant;
+
+    use futures::future::{BoxFuture, FutureExt};
+    use tracing::{field, Instrument, Span};
+
+    use crate::ActorContext;
+
+    fn micros(since: Instant) -> u64 {
+        since.elapsed().as_micros().min(u64::MAX as u128) as u64
+    }
+
+    // Called once the actor starts running the message.
+    pub(super) fn message_span<T: ?Sized>(
+        parent: &Span,
+        method: &'static str,
+        sent: Instant,
+        ctx: &ActorContext<T>,
+    ) -> Span {
+        let span = tracing::debug_span!(
+            parent: parent,
+            "message",
+            actor.id = field::Empty,
+            actor.name = field::Empty,
+            method,
+            queue_wait_us = micros(sent),
+            exec_us = field::Empty,
+        );
+        if let Some(shared) = ctx.shared() {
+            span.record("actor.id", field::display(shared.identity.id));
+            if let Some(name) = &shared.identity.name {
+                span.record("actor.name", &**name);
+            }
+        }
+        span
+    }
+
+    pub(super) fn timed<'a, R: 'a>(span: Span, fut: BoxFuture<'a, R>) -> BoxFuture<'a, R> {
+        async move {
+            let started = Instant::now();
+            let res = fut.instrument(span.clone()).await;
+            span.record("exec_us", micros(started));
+            res
+        }
+        .boxed()
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn instrument<T: ?Sized + 'static>(method: &'static str, run: RunFn<T>) -> RunFn<T> {
+    let parent = tracing::Span::current();
+    let sent = std::time::Instant::now();
+    Box::new(move |x, ctx| {
+        let span = imp::message_span(&parent, method, sent, &ctx);
+        let fut = span.in_scope(move || run(x, ctx));
+        imp::timed(span, fut)
+    })
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn instrument_ref<T: ?Sized + 'static>(
+    method: &'static str,
+    run: RefRunFn<T>,
+) -> RefRunFn<T> {
+    let parent = tracing::Span::current();
+    let sent = std::time::Instant::now();
+    Box::new(move |x, ctx| {
+        let span = imp::message_span(&parent, method, sent, &ctx);
+        let fut = span.in_scope(move || run(x, ctx));
+        imp::timed(span, fut)
+    })
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn instrument<T: ?Sized>(_method: &'static str, run: RunFn<T>) -> RunFn<T> {
+    run
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn instrument_ref<T: ?Sized>(_method: &'static str, run: RefRunFn<T>) -> RefRunFn<T> {
+    run
+}
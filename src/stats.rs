@@ -0,0 +1,97 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Weak;
+use std::time::{Duration, Instant};
+
+use crate::addr::Shared;
+
+// Counters updated by the actor task, which can be read from any thread.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    futures: AtomicUsize,
+    processed: AtomicU64,
+    errors: AtomicU64,
+    busy_nanos: AtomicU64,
+    busy: AtomicBool,
+}
+
+impl Stats {
+    pub(crate) fn set_futures(&self, futures: usize) {
+        self.futures.store(futures, Ordering::Relaxed);
+    }
+    pub(crate) fn record_message(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+    pub(crate) fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+    pub(crate) fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+    // Marks the actor as busy, returning the time at which it started.
+    pub(crate) fn begin(&self) -> Instant {
+        self.busy.store(true, Ordering::Relaxed);
+        Instant::now()
+    }
+    pub(crate) fn end(&self, began: Instant) {
+        let elapsed = began.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+        self.busy_nanos.fetch_add(elapsed, Ordering::Relaxed);
+        self.busy.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A handle which can be used to inspect the state of an actor whilst it is
+/// running, without sending it a message. Obtained using `Addr::stats` or
+/// `WeakAddr::stats`.
+///
+/// The values are updated as the actor runs, so may already be out of date
+/// by the time they are read. The handle does not keep the actor alive, and
+/// a handle to a detached address reports zero for everything.
+#[derive(Debug, Clone, Default)]
+pub struct ActorStats {
+    shared: Weak<Shared>,
+}
+
+impl ActorStats {
+    pub(crate) fn new(shared: Weak<Shared>) -> Self {
+        Self { shared }
+    }
+
+    fn read<R: Default>(&self, f: impl FnOnce(&Shared) -> R) -> R {
+        self.shared
+            .upgrade()
+            .map(|shared| f(&shared))
+            .unwrap_or_default()
+    }
+
+    /// The number of messages waiting to be processed by the actor.
+    pub fn queue_len(&self) -> usize {
+        self.read(|shared| shared.mailbox.len())
+    }
+
+    /// The number of futures spawned onto the actor via `send_fut` which are
+    /// still running.
+    pub fn futures(&self) -> usize {
+        self.read(|shared| shared.stats.futures.load(Ordering::Relaxed))
+    }
+
+    /// The number of messages the actor has finished processing.
+    pub fn processed(&self) -> u64 {
+        self.read(|shared| shared.stats.processed.load(Ordering::Relaxed))
+    }
+
+    /// The number of errors which have been passed to `Actor::error`.
+    pub fn errors(&self) -> u64 {
+        self.read(|shared| shared.stats.errors.load(Ordering::Relaxed))
+    }
+
+    /// The total time the actor has spent processing messages, excluding the
+    /// message it is currently processing.
+    pub fn busy_time(&self) -> Duration {
+        Duration::from_nanos(self.read(|shared| shared.stats.busy_nanos.load(Ordering::Relaxed)))
+    }
+
+    /// Returns `true` if the actor is currently processing a message.
+    pub fn is_busy(&self) -> bool {
+        self.read(|shared| shared.stats.busy.load(Ordering::Relaxed))
+    }
+}
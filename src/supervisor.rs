@@ -0,0 +1,286 @@
+//! Functionality related to supervision.
+//!
+//! A `Supervisor` is an actor which restarts its children when they fail.
+//! Children are restarted in place, so existing addresses continue to work
+//! across restarts, and messages queued for a failed child are delivered to
+//! its replacement.
+//!
+//! Supervisors require support from a runtime implementing both the `Spawn`
+//! and `SupportsTimers` traits.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use futures::future::FutureExt;
+use futures::task::Spawn;
+use log::{error, warn};
+
+use crate::addr::RestartFn;
+use crate::timer::SupportsTimers;
+use crate::{
+    call, upcast, Actor, ActorContext, ActorResult, Addr, ExitReason, Produces, SpawnOptions,
+    WeakAddr,
+};
+
+type Factory<T> = Arc<Mutex<dyn FnMut() -> T + Send>>;
+
+// Creates a new instance of a child. A factory which panicked previously
+// is still used.
+fn create<T>(factory: &Factory<T>) -> T {
+    (factory.lock().unwrap_or_else(|e| e.into_inner()))()
+}
+
+fn lock<T>(slot: &Mutex<WeakAddr<T>>) -> MutexGuard<'_, WeakAddr<T>> {
+    slot.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Determines which children are restarted when a child fails.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RestartStrategy {
+    /// Only the failed child is restarted.
+    OneForOne,
+    /// All children are restarted.
+    OneForAll,
+    /// The failed child is restarted, along with all children which
+    /// were added after it.
+    RestForOne,
+}
+
+#[derive(Debug)]
+struct Child {
+    addr: WeakAddr<dyn Actor>,
+    // Set when the supervisor kills this child in order to restart it.
+    restarting: bool,
+    // When this child was last due to be restarted.
+    restart_at: Option<Instant>,
+}
+
+/// An actor which restarts its children when they fail.
+///
+/// Children are restarted whenever they stop for an abnormal reason: that is,
+/// when `Actor::error` asks them to stop, when a lifecycle method panics, or
+/// when they are killed. Children which stop normally are not restarted.
+///
+/// If children fail too many times within the configured time window, the
+/// supervisor kills all of its children and stops with `ExitReason::Error`,
+/// so that the failure is reported to anything linked to or monitoring it.
+///
+/// ```ignore
+/// let supervisor = spawn_actor(Supervisor::new(Runtime, RestartStrategy::OneForOne));
+/// let addr = call!(supervisor.supervise(|| MyActor::default())).await?;
+/// ```
+#[derive(Debug)]
+pub struct Supervisor<R: 'static> {
+    runtime: R,
+    strategy: RestartStrategy,
+    max_restarts: usize,
+    window: Duration,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    restarts: VecDeque<Instant>,
+    // Keyed by an id which increases in the order the children were added.
+    children: BTreeMap<usize, Child>,
+    next_id: usize,
+    addr: WeakAddr<Self>,
+}
+
+#[async_trait]
+impl<R: Send + 'static> Actor for Supervisor<R> {
+    async fn started(&mut self, addr: Addr<Self>) -> ActorResult<()> {
+        self.addr = addr.downgrade();
+        Produces::ok(())
+    }
+}
+
+impl<R: Spawn + SupportsTimers + Clone + Send + 'static> Supervisor<R> {
+    /// Construct a new supervisor with the provided runtime and strategy.
+    ///
+    /// By default, children may be restarted up to 3 times in any 5 second
+    /// window, and are restarted immediately.
+    pub fn new(runtime: R, strategy: RestartStrategy) -> Self {
+        Self {
+            runtime,
+            strategy,
+            max_restarts: 3,
+            window: Duration::from_secs(5),
+            min_backoff: Duration::from_secs(0),
+            max_backoff: Duration::from_secs(0),
+            restarts: VecDeque::new(),
+            children: BTreeMap::new(),
+            next_id: 0,
+            addr: WeakAddr::detached(),
+        }
+    }
+    /// Limit the number of restarts which may occur within a time window.
+    pub fn with_max_restarts(mut self, max_restarts: usize, window: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.window = window;
+        self
+    }
+    /// Delay restarts using exponential backoff. The first restart within the time
+    /// window is delayed by `min`, and the delay doubles with each subsequent
+    /// restart, up to `max`.
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Spawn a child actor under this supervisor. The factory is called to create
+    /// the actor, and again each time the actor needs to be restarted.
+    pub async fn supervise<T: Actor>(
+        &mut self,
+        factory: impl FnMut() -> T + Send + 'static,
+    ) -> ActorResult<Addr<T>> {
+        self.supervise_with(SpawnOptions::default(), factory).await
+    }
+
+    /// Spawn a child actor under this supervisor with the given options. The
+    /// options continue to apply when the actor is restarted.
+    pub async fn supervise_with<T: Actor>(
+        &mut self,
+        options: SpawnOptions,
+        factory: impl FnMut() -> T + Send + 'static,
+    ) -> ActorResult<Addr<T>> {
+        // Forget about children which have stopped for good.
+        self.children.retain(|_, child| child.addr.is_alive());
+        let id = self.next_id;
+        self.next_id += 1;
+        let factory: Factory<T> = Arc::new(Mutex::new(factory));
+        let slot = Arc::new(Mutex::new(WeakAddr::detached()));
+
+        let supervisor = self.addr.clone();
+        let runtime = self.runtime.clone();
+        let factory2 = factory.clone();
+        let slot2 = slot.clone();
+        let restart: RestartFn<T> = Box::new(move |reason, ctx| {
+            restart_child(
+                supervisor.clone(),
+                runtime.clone(),
+                factory2.clone(),
+                slot2.clone(),
+                id,
+                reason,
+                ctx,
+            )
+            .boxed()
+        });
+
+        let value = create(&factory);
+        let addr = Addr::new_restartable(&self.runtime, value, options, restart)?;
+        *lock(&slot) = addr.downgrade();
+        self.children.insert(
+            id,
+            Child {
+                addr: upcast!(addr.downgrade()),
+                restarting: false,
+                restart_at: None,
+            },
+        );
+        Produces::ok(addr)
+    }
+
+    fn backoff(&self, restarts: usize) -> Duration {
+        let factor = 2u32.saturating_pow(restarts.saturating_sub(1) as u32);
+        self.min_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    // Called when a child stops abnormally. Returns when the child should be
+    // restarted, or `None` if it should remain stopped. Returns an error, which
+    // stops the supervisor, if children have failed too many times.
+    async fn child_failed(
+        &mut self,
+        id: usize,
+        reason: ExitReason,
+    ) -> ActorResult<Option<Instant>> {
+        let now = self.runtime.now();
+        let child = match self.children.get_mut(&id) {
+            Some(child) => child,
+            None => return Produces::ok(None),
+        };
+        if mem::replace(&mut child.restarting, false) {
+            // We killed this child ourselves, so this doesn't count as a failure.
+            return Produces::ok(child.restart_at);
+        }
+
+        while let Some(&time) = self.restarts.front() {
+            if now.duration_since(time) < self.window {
+                break;
+            }
+            self.restarts.pop_front();
+        }
+        if self.restarts.len() >= self.max_restarts {
+            // Kill the remaining children, including those which are waiting to
+            // be restarted. They remain stopped, as the supervisor is gone by the
+            // time they report their failure.
+            for (&index, child) in &self.children {
+                if index != id {
+                    child.addr.kill();
+                }
+            }
+            return Err(format!("Supervised actor stopped ({:?}) too many times", reason).into());
+        }
+        warn!("Supervised actor stopped ({:?}), restarting", reason);
+        self.restarts.push_back(now);
+        let deadline = now + self.backoff(self.restarts.len());
+
+        let siblings = match self.strategy {
+            RestartStrategy::OneForOne => id..id,
+            RestartStrategy::OneForAll => 0..usize::MAX,
+            RestartStrategy::RestForOne => id..usize::MAX,
+        };
+        for (&index, sibling) in self.children.range_mut(siblings) {
+            // Skip children which are already waiting to be restarted.
+            let running = sibling.restart_at.map_or(true, |at| at <= now);
+            if index != id && running {
+                sibling.restarting = true;
+                sibling.restart_at = Some(deadline);
+                sibling.addr.kill();
+            }
+        }
+        if let Some(child) = self.children.get_mut(&id) {
+            child.restart_at = Some(deadline);
+        }
+        Produces::ok(Some(deadline))
+    }
+}
+
+async fn restart_child<T: Actor, R: Spawn + SupportsTimers + Clone + Send + 'static>(
+    supervisor: WeakAddr<Supervisor<R>>,
+    runtime: R,
+    factory: Factory<T>,
+    slot: Arc<Mutex<WeakAddr<T>>>,
+    id: usize,
+    mut reason: ExitReason,
+    ctx: ActorContext<T>,
+) -> Option<T> {
+    loop {
+        let deadline = call!(supervisor.child_failed(id, reason)).await.ok()??;
+        runtime.delay(deadline).await;
+
+        // Failures are handled in the same way as when the child was first spawned.
+        let mut value = match panic::catch_unwind(AssertUnwindSafe(|| create(&factory))) {
+            Ok(value) => value,
+            Err(_) => {
+                error!("Supervised actor factory panicked");
+                reason = ExitReason::Panicked;
+                continue;
+            }
+        };
+        let addr = lock(&slot).upgrade();
+        let res = crate::hidden::run_method(&ctx, "started", value.started(addr)).await;
+        if let Err(e) = res.and_then(|res| res) {
+            if let Some(stopped) = crate::hidden::handle_error(&mut value, &ctx, e).await {
+                reason = stopped;
+                continue;
+            }
+        }
+        return Some(value);
+    }
+}
@@ -10,7 +10,7 @@ use async_trait::async_trait;
 use futures::future::FutureExt;
 use futures::{pin_mut, select_biased};
 
-use crate::{send, upcast, Actor, ActorResult, Addr, AddrLike, WeakAddr};
+use crate::{send_async, upcast, Actor, ActorResult, Addr, AddrLike, WeakAddr};
 
 /// Timers can be used on runtimes implementing this trait.
 pub trait SupportsTimers {
@@ -20,6 +20,12 @@ pub trait SupportsTimers {
     /// Create a future which will complete when the deadline
     /// is passed.
     fn delay(&self, deadline: Instant) -> Self::Delay;
+
+    /// The current time, as measured by the clock used for delays. Defaults
+    /// to the system clock.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
 }
 
 /// Provides an actor with a "tick" method, that will be called whenever
@@ -45,9 +51,10 @@ pub trait Tick: Actor {
 }
 
 /// Timers will be in one of these states.
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
 pub enum TimerState {
     /// The timer is inactive. This is the default state.
+    #[default]
     Inactive,
     /// The timer is configured to tick once, when the deadline
     /// is reached.
@@ -85,14 +92,9 @@ impl TimerState {
     }
 }
 
-impl Default for TimerState {
-    fn default() -> Self {
-        Self::Inactive
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Default)]
 enum InternalTimerState {
+    #[default]
     Inactive,
     Timeout {
         deadline: Instant,
@@ -109,12 +111,6 @@ enum InternalTimerState {
     },
 }
 
-impl Default for InternalTimerState {
-    fn default() -> Self {
-        Self::Inactive
-    }
-}
-
 impl InternalTimerState {
     fn public_state(&self) -> TimerState {
         match *self {
@@ -162,7 +158,7 @@ impl<R: SupportsTimers> Timer<R> {
         match mem::replace(&mut self.state, InternalTimerState::Inactive) {
             InternalTimerState::Inactive => false,
             InternalTimerState::Timeout { deadline } => {
-                if deadline <= Instant::now() {
+                if deadline <= self.runtime.now() {
                     true
                 } else {
                     self.state = InternalTimerState::Timeout { deadline };
@@ -174,7 +170,7 @@ impl<R: SupportsTimers> Timer<R> {
                 interval,
                 addr,
             } => {
-                if deadline <= Instant::now() {
+                if deadline <= self.runtime.now() {
                     self.set_interval_at_weak_internal(addr, deadline + interval, interval);
                     true
                 } else {
@@ -191,7 +187,7 @@ impl<R: SupportsTimers> Timer<R> {
                 interval,
                 addr,
             } => {
-                if deadline <= Instant::now() {
+                if deadline <= self.runtime.now() {
                     self.set_interval_at_strong_internal(addr, deadline + interval, interval);
                     true
                 } else {
@@ -215,7 +211,8 @@ impl<R: SupportsTimers> Timer<R> {
         let delay = self.runtime.delay(start);
         addr.send_fut(async move {
             delay.await;
-            send!(addr2.tick());
+            // Wait for space rather than dropping the tick if the mailbox is full.
+            let _ = send_async!(addr2.tick()).await;
         });
 
         self.state = InternalTimerState::IntervalWeak {
@@ -234,7 +231,7 @@ impl<R: SupportsTimers> Timer<R> {
         let delay = self.runtime.delay(start);
         addr.send_fut(async move {
             delay.await;
-            send!(addr2.tick());
+            let _ = send_async!(addr2.tick()).await;
         });
 
         self.state = InternalTimerState::IntervalStrong {
@@ -252,7 +249,7 @@ impl<R: SupportsTimers> Timer<R> {
         let delay = self.runtime.delay(deadline);
         addr.send_fut(async move {
             delay.await;
-            send!(addr2.tick());
+            let _ = send_async!(addr2.tick()).await;
         });
 
         self.state = InternalTimerState::Timeout { deadline };
@@ -279,7 +276,7 @@ impl<R: SupportsTimers> Timer<R> {
                 // Future completed first, so wait for delay too
                 delay.await;
             }
-            send!(addr2.tick());
+            let _ = send_async!(addr2.tick()).await;
         });
 
         self.state = InternalTimerState::Timeout { deadline };
@@ -308,12 +305,12 @@ impl<R: SupportsTimers> Timer<R> {
     /// Configure the timer to tick at a set interval, with the initial tick sent immediately.
     /// The timer will not try to keep the actor alive.
     pub fn set_interval_weak<T: Tick>(&mut self, addr: WeakAddr<T>, interval: Duration) {
-        self.set_interval_at_weak_internal(upcast!(addr), Instant::now(), interval);
+        self.set_interval_at_weak_internal(upcast!(addr), self.runtime.now(), interval);
     }
     /// Configure the timer to tick at a set interval, with the initial tick sent immediately.
     /// The timer will try to keep the actor alive.
     pub fn set_interval_strong<T: Tick>(&mut self, addr: Addr<T>, interval: Duration) {
-        self.set_interval_at_strong_internal(upcast!(addr), Instant::now(), interval);
+        self.set_interval_at_strong_internal(upcast!(addr), self.runtime.now(), interval);
     }
     /// Configure the timer to tick once at the specified time.
     /// The timer will not try to keep the actor alive.
@@ -328,12 +325,12 @@ impl<R: SupportsTimers> Timer<R> {
     /// Configure the timer to tick once after a delay.
     /// The timer will not try to keep the actor alive.
     pub fn set_timeout_for_weak<T: Tick>(&mut self, addr: WeakAddr<T>, duration: Duration) {
-        self.set_timeout_internal(addr, Instant::now() + duration);
+        self.set_timeout_internal(addr, self.runtime.now() + duration);
     }
     /// Configure the timer to tick once after a delay.
     /// The timer will try to keep the actor alive until that time.
     pub fn set_timeout_for_strong<T: Tick>(&mut self, addr: Addr<T>, duration: Duration) {
-        self.set_timeout_internal(addr, Instant::now() + duration);
+        self.set_timeout_internal(addr, self.runtime.now() + duration);
     }
     /// Configure the timer to tick once at the specified time, whilst simultaneously
     /// running a task to completion. If the timeout completes first, the task will
@@ -369,7 +366,7 @@ impl<R: SupportsTimers> Timer<R> {
         duration: Duration,
         f: impl FnOnce(WeakAddr<T>) -> F + Send + 'static,
     ) {
-        self.run_with_timeout_internal(addr, Instant::now() + duration, f);
+        self.run_with_timeout_internal(addr, self.runtime.now() + duration, f);
     }
     /// Configure the timer to tick once at the specified time, whilst simultaneously
     /// running a task to completion. If the timeout completes first, the task will
@@ -384,6 +381,6 @@ impl<R: SupportsTimers> Timer<R> {
         duration: Duration,
         f: impl FnOnce(Addr<T>) -> F + Send + 'static,
     ) {
-        self.run_with_timeout_internal(addr, Instant::now() + duration, f);
+        self.run_with_timeout_internal(addr, self.runtime.now() + duration, f);
     }
 }
@@ -1,20 +1,193 @@
+use std::fmt::{self, Debug};
 use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
 
+use futures::channel::{mpsc, oneshot};
 use futures::future::FutureExt;
+use futures::stream::{BoxStream, Stream, StreamExt};
+use futures::{select_biased, SinkExt};
 
-use crate::Produces;
+use crate::addr::Identity;
+use crate::{ActorResult, AddrLike, ExitReason, Produces};
 
-/// A future which completes upon termination of an actor.
+/// A future which completes upon termination of an actor, and resolves
+/// to the reason the actor stopped.
 #[derive(Debug)]
-pub struct Termination(pub(crate) Produces<()>);
+pub struct Termination {
+    // Resolves with a value if the reason is not recorded in `identity`.
+    done: Produces<ExitReason>,
+    identity: Option<Arc<Identity>>,
+}
+
+impl Termination {
+    pub(crate) fn new(done: Produces<ExitReason>, identity: Option<Arc<Identity>>) -> Self {
+        Self { done, identity }
+    }
+}
 
 impl Future for Termination {
-    type Output = ();
+    type Output = ExitReason;
 
     fn poll(
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Self::Output> {
-        self.0.poll_unpin(cx).map(|_| ())
+        self.done.poll_unpin(cx).map(|res| {
+            res.ok()
+                .or_else(|| {
+                    self.identity
+                        .as_ref()
+                        .and_then(|identity| identity.exit_reason())
+                })
+                .unwrap_or(ExitReason::Normal)
+        })
+    }
+}
+
+/// A stream of values returned from an actor method to a caller using the
+/// `call_stream!(...)` macro.
+///
+/// The stream is polled on the actor's task, and its values are buffered
+/// until the caller is ready to receive them. Once the buffer is full, the
+/// stream will not be polled again until the caller catches up.
+pub struct Streaming<T> {
+    stream: BoxStream<'static, T>,
+    buffer: usize,
+}
+
+impl<T> Debug for Streaming<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Streaming")
+            .field("buffer", &self.buffer)
+            .finish()
     }
 }
+
+impl<T> Streaming<T> {
+    /// Wrap a stream, using the default buffer size of 16 values.
+    pub fn new(stream: impl Stream<Item = T> + Send + 'static) -> Self {
+        Self {
+            stream: stream.boxed(),
+            buffer: 16,
+        }
+    }
+    /// Returns `Ok(Produces::Value(Streaming::new(stream)))`
+    pub fn ok(stream: impl Stream<Item = T> + Send + 'static) -> ActorResult<Self> {
+        Produces::ok(Self::new(stream))
+    }
+    /// Set the number of values which may be buffered before the caller
+    /// receives them.
+    pub fn with_buffer(mut self, buffer: usize) -> Self {
+        self.buffer = buffer;
+        self
+    }
+}
+
+#[derive(Debug)]
+enum ProducesStreamState<T> {
+    Waiting(oneshot::Receiver<mpsc::Receiver<T>>),
+    Receiving(mpsc::Receiver<T>),
+    Done,
+}
+
+/// A stream of values produced by an actor method, returned by the
+/// `call_stream!(...)` macro.
+///
+/// The stream ends early if the actor method fails, or if the actor stops.
+/// Dropping this stream cancels the stream on the actor side.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct ProducesStream<T> {
+    state: ProducesStreamState<T>,
+    // Signals to the actor that the stream has been dropped.
+    _cancel: oneshot::Receiver<()>,
+}
+
+impl<T: Send + 'static> ProducesStream<T> {
+    pub(crate) fn new<A: AddrLike>(addr: &A, res: Produces<Streaming<T>>) -> Self {
+        let (rx_tx, rx_rx) = oneshot::channel();
+        let (mut cancel_tx, cancel_rx) = oneshot::channel();
+        addr.send_fut(async move {
+            let streaming = match res.await {
+                Ok(streaming) => streaming,
+                Err(_) => return,
+            };
+            let (tx, rx) = mpsc::channel(streaming.buffer);
+            if rx_tx.send(rx).is_err() {
+                return;
+            }
+            let mut forward = streaming
+                .stream
+                .map(Ok)
+                .forward(tx.sink_map_err(drop))
+                .fuse();
+            select_biased! {
+                _ = cancel_tx.cancellation().fuse() => {}
+                _ = forward => {}
+            }
+        });
+        Self {
+            state: ProducesStreamState::Waiting(rx_rx),
+            _cancel: cancel_rx,
+        }
+    }
+}
+
+impl<T> Unpin for ProducesStream<T> {}
+
+impl<T> Stream for ProducesStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        loop {
+            break match &mut self.state {
+                ProducesStreamState::Waiting(rx) => match rx.poll_unpin(cx) {
+                    Poll::Ready(Ok(rx)) => {
+                        self.state = ProducesStreamState::Receiving(rx);
+                        continue;
+                    }
+                    Poll::Ready(Err(_)) => {
+                        self.state = ProducesStreamState::Done;
+                        Poll::Ready(None)
+                    }
+                    Poll::Pending => Poll::Pending,
+                },
+                ProducesStreamState::Receiving(rx) => {
+                    let res = rx.poll_next_unpin(cx);
+                    if let Poll::Ready(None) = res {
+                        self.state = ProducesStreamState::Done;
+                    }
+                    res
+                }
+                ProducesStreamState::Done => Poll::Ready(None),
+            };
+        }
+    }
+}
+
+const SPLITMIX_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// A small, fast pseudo-random number generator, which is good enough for
+// scheduling and load balancing decisions.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(SPLITMIX_GAMMA);
+    splitmix64_mix(*state)
+}
+
+// As `splitmix64`, but the state may be shared between threads.
+pub(crate) fn splitmix64_atomic(state: &AtomicU64) -> u64 {
+    splitmix64_mix(
+        state
+            .fetch_add(SPLITMIX_GAMMA, Ordering::Relaxed)
+            .wrapping_add(SPLITMIX_GAMMA),
+    )
+}
+
+fn splitmix64_mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
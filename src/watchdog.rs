@@ -0,0 +1,144 @@
+//! Functionality related to detecting slow actor methods.
+//!
+//! Whilst an actor method is running, the actor cannot process any other
+//! messages. A `Watchdog` can be attached to an actor when it is spawned,
+//! using `SpawnOptions::watchdog`, in order to find methods which block or
+//! await for too long.
+//!
+//! When a method runs for longer than its threshold, a warning is logged
+//! with the name of the actor and the method. The watchdog can optionally
+//! cancel the method as well, in which case the method is dropped, a
+//! `HandlerTimeout` error is passed to `Actor::error`, and the caller
+//! receives `ProducesError::TimedOut`.
+//!
+//! ```ignore
+//! let watchdog = Watchdog::new(Runtime, Duration::from_secs(1))
+//!     .method("reindex", Duration::from_secs(30))
+//!     .cancel(true);
+//! let options = SpawnOptions::new().watchdog(watchdog);
+//! let addr = Addr::new_with(&Runtime, MyActor::default(), options)?;
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{BoxFuture, FutureExt};
+use futures::select_biased;
+use log::warn;
+
+use crate::addr::Shared;
+use crate::timer::SupportsTimers;
+
+// Returns a future which completes once the given duration has elapsed.
+type DelayFn = dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync;
+
+/// Error passed to `Actor::error` when a method is canceled by a watchdog.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HandlerTimeout {
+    method: &'static str,
+    threshold: Duration,
+}
+
+impl HandlerTimeout {
+    /// The name of the method which was canceled.
+    pub fn method(&self) -> &'static str {
+        self.method
+    }
+    /// How long the method was allowed to run for.
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+}
+
+impl fmt::Display for HandlerTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Actor method `{}` was canceled after {:?}",
+            self.method, self.threshold
+        )
+    }
+}
+
+impl Error for HandlerTimeout {}
+
+/// Watches the methods of an actor, and warns about those which run for too
+/// long. See the module documentation for details.
+#[derive(Clone)]
+pub struct Watchdog {
+    delay: Arc<DelayFn>,
+    threshold: Duration,
+    methods: HashMap<&'static str, Duration>,
+    cancel: bool,
+}
+
+impl Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("threshold", &self.threshold)
+            .field("methods", &self.methods)
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl Watchdog {
+    /// Construct a new watchdog which warns about any method running for longer
+    /// than `threshold`. The runtime is used to wait for the threshold to elapse.
+    pub fn new<R: SupportsTimers + Send + Sync + 'static>(runtime: R, threshold: Duration) -> Self {
+        Self {
+            delay: Arc::new(move |duration| runtime.delay(runtime.now() + duration).boxed()),
+            threshold,
+            methods: HashMap::new(),
+            cancel: false,
+        }
+    }
+
+    /// Use a different threshold for the method with the given name.
+    pub fn method(mut self, method: &'static str, threshold: Duration) -> Self {
+        self.methods.insert(method, threshold);
+        self
+    }
+
+    /// Whether methods should be canceled once they exceed their threshold.
+    /// Defaults to `false`, in which case methods are allowed to continue
+    /// running after the warning has been logged.
+    pub fn cancel(mut self, cancel: bool) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    // Runs an actor method, warning if it exceeds its threshold. Returns an
+    // error if the method was canceled.
+    pub(crate) async fn watch<F: Future>(
+        &self,
+        shared: &Shared,
+        method: &'static str,
+        fut: F,
+    ) -> Result<F::Output, HandlerTimeout> {
+        let threshold = self.methods.get(method).copied().unwrap_or(self.threshold);
+        let fut = fut.fuse();
+        futures::pin_mut!(fut);
+        select_biased! {
+            res = fut => return Ok(res),
+            _ = (self.delay)(threshold).fuse() => {}
+        }
+        warn!(
+            "{}[{}{}]::{} has been running for longer than {:?}",
+            shared.actor,
+            shared.identity.name.as_deref().unwrap_or(""),
+            shared.identity.id,
+            method,
+            threshold
+        );
+        if self.cancel {
+            Err(HandlerTimeout { method, threshold })
+        } else {
+            Ok(fut.await)
+        }
+    }
+}